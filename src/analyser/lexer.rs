@@ -7,7 +7,7 @@ use std::{str, vec};
 use bytes::complete::{is_a, take_while};
 use combinator::{fail, map_res};
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take, take_till1, take_while_m_n};
+use nom::bytes::complete::{tag, take, take_till1, take_while1, take_while_m_n};
 use nom::character::complete::{alphanumeric1, anychar, char as char1, digit1, multispace0};
 use nom::combinator::{cond, map, opt, recognize};
 use nom::multi::{many0, separated_list0};
@@ -17,6 +17,7 @@ use nom::*;
 use sequence::preceded;
 
 use super::token::{Color, MidiMsg, TimeMsg, Token};
+use crate::time;
 
 // ------- custom error handling for fault-torelant parser ----------
 // https://eyalkalderon.com/blog/nom-error-recovery/
@@ -83,6 +84,21 @@ impl Error {
       self.3.clone(),
     )
   }
+
+  /// The byte range of the offending input.
+  pub fn range(&self) -> Range<usize> {
+    self.0.clone()
+  }
+
+  /// The offending input.
+  pub fn input(&self) -> &str {
+    &self.1
+  }
+
+  /// What is wrong with the input.
+  pub fn message(&self) -> &str {
+    &self.2
+  }
 }
 
 #[derive(Clone, Debug)]
@@ -142,15 +158,25 @@ macro_rules! syntax {
 syntax! {comma_punctuation, ",", Token::Comma}
 syntax! {lbracket_punctuation, "[", Token::LBracket}
 syntax! {rbracket_punctuation, "]", Token::RBracket}
+syntax! {lbrace_punctuation, "{", Token::LBrace}
+syntax! {rbrace_punctuation, "}", Token::RBrace}
+syntax! {semicolon_punctuation, ";", Token::Semicolon}
 
 pub fn lex_punctuations(input: LocatedSpan) -> IResult<Token> {
   alt((
     comma_punctuation,
     lbracket_punctuation,
     rbracket_punctuation,
+    lbrace_punctuation,
+    rbrace_punctuation,
+    semicolon_punctuation,
   ))(input)
 }
 
+// --------- keywords ---------
+
+syntax! {bundle_keyword, "#bundle", Token::Bundle}
+
 // --------- String ---------
 fn pis(input: LocatedSpan) -> IResult<Vec<u8>> {
   let inp = input.clone();
@@ -279,10 +305,15 @@ fn lex_reserved_ident(input: LocatedSpan) -> IResult<Token> {
 // --------- osc_path ---------
 
 fn osc_method_segment(input: LocatedSpan) -> IResult<String> {
+  // `;` and braces are left out so that bundle elements can be separated, or the bundle closed,
+  // right after an address. Braces are kept when they hold `{foo,bar}` pattern alternatives.
+  let segment_char =
+    |b: char| (b.is_alphanumeric() || b.is_ascii_punctuation()) && !"/;{}".contains(b);
   map(
-    alt((take_while(|b: char| {
-      (b.is_alphanumeric() || b.is_ascii_punctuation()) && b != '/'
-    }),)),
+    recognize(many0(alt((
+      take_while1(segment_char),
+      recognize(delimited(char1('{'), take_while(segment_char), char1('}'))),
+    )))),
     |s: LocatedSpan| s.fragment().to_string(),
   )(input)
 }
@@ -496,32 +527,135 @@ fn parse_seconds(input: LocatedSpan) -> IResult<u32> {
   Ok((input, seconds))
 }
 
-fn parse_fractional(input: LocatedSpan) -> IResult<u32> {
+// The digits after the decimal point, converted to a fraction of a second in units of 2^-32.
+fn parse_fractional(input: LocatedSpan) -> IResult<u64> {
   let (input, fractional_str) =
     opt(terminated(parse_digits_with_underscores, many0(char1(' '))))(input)?;
 
   let fractional = fractional_str.unwrap_or_else(|| "".to_string());
-  let fractional = if !fractional.is_empty() {
-    fractional.parse::<u32>().unwrap_or(0)
-  } else {
-    0
-  };
-
-  Ok((input, fractional))
+  Ok((input, time::fraction_from_decimal(&fractional)))
 }
 
 fn parse_time_segment(input: LocatedSpan) -> IResult<(u32, u32)> {
   let mut parser = alt((map(
     tuple((parse_seconds, char1('.'), parse_fractional)),
-    |(seconds, _, fractional): (u32, char, u32)| (seconds, fractional),
+    |(seconds, _, fractional): (u32, char, u64)| {
+      // A fraction rounding up to a whole second carries over into the seconds.
+      let fixed_point = ((seconds as u64) << 32) + fractional;
+      ((fixed_point >> 32) as u32, fixed_point as u32)
+    },
   ),));
 
   parser(input)
 }
 
+fn two_digits(input: LocatedSpan) -> IResult<u32> {
+  map(
+    take_while_m_n(2, 2, |c: char| c.is_ascii_digit()),
+    |s: LocatedSpan| s.fragment().parse::<u32>().unwrap_or(0),
+  )(input)
+}
+
+// `Z`, `+HH:MM` or `-HH:MM`, as seconds east of UTC.
+fn parse_utc_offset(input: LocatedSpan) -> IResult<i64> {
+  alt((
+    map(char1('Z'), |_| 0),
+    map(
+      tuple((
+        alt((char1('+'), char1('-'))),
+        two_digits,
+        char1(':'),
+        two_digits,
+      )),
+      |(sign, hours, _, minutes)| {
+        let offset = (hours * 3_600 + minutes * 60) as i64;
+        if sign == '-' {
+          -offset
+        } else {
+          offset
+        }
+      },
+    ),
+  ))(input)
+}
+
+// ISO-8601 date and time, eg. `2026-10-17T20:00:00.5Z` or `2026-10-17T22:00:00+02:00`.
+fn parse_iso_time(input: LocatedSpan) -> IResult<(u32, u32)> {
+  let (remaining, (year, _, month, _, day, _, hour, _, minute, _, second, fraction, offset)) =
+    tuple((
+      take_while_m_n(4, 4, |c: char| c.is_ascii_digit()),
+      char1('-'),
+      two_digits,
+      char1('-'),
+      two_digits,
+      char1('T'),
+      two_digits,
+      char1(':'),
+      two_digits,
+      char1(':'),
+      two_digits,
+      opt(preceded(char1('.'), digit1)),
+      parse_utc_offset,
+    ))(input.clone())?;
+  let year = year.fragment().parse::<i64>().unwrap_or(0);
+  let valid = (1..=12).contains(&month)
+    && (1..=time::days_in_month(year, month)).contains(&day)
+    && hour < 24
+    && minute < 60
+    && second < 60;
+  if !valid {
+    return Err(nom::Err::Error(nom::error::Error::new(
+      input,
+      nom::error::ErrorKind::Verify,
+    )));
+  }
+  let fraction = fraction.map_or(0, |digits: LocatedSpan| {
+    time::fraction_from_decimal(digits.fragment())
+  });
+  let ntp = time::ntp_from_utc((year, month, day), (hour, minute, second), fraction, offset);
+  Ok((remaining, (ntp.seconds, ntp.fractional)))
+}
+
+// `+250ms` or `-1.5s`, the unit defaults to seconds.
+fn parse_time_offset(input: LocatedSpan) -> IResult<(char, LocatedSpan)> {
+  pair(
+    alt((char1('+'), char1('-'))),
+    recognize(pair(
+      take_while1(|c: char| c.is_ascii_digit() || c == '.' || c == '_'),
+      opt(alt((tag("ms"), tag("s"), tag("m"), tag("h")))),
+    )),
+  )(input)
+}
+
+// `now`, `now+250ms`, `now-1s` or `+1.5s`, as signed nanoseconds from the time of sending.
+fn parse_relative_time(input: LocatedSpan) -> IResult<i64> {
+  let (remaining, offset) = alt((
+    preceded(tag("now"), opt(parse_time_offset)),
+    map(parse_time_offset, Some),
+  ))(input.clone())?;
+  let (sign, duration) = match offset {
+    Some(offset) => offset,
+    None => return Ok((remaining, 0)),
+  };
+  // Offsets beyond about 292 years do not fit in the nanoseconds.
+  let nanos = time::parse_duration(duration.fragment())
+    .ok()
+    .and_then(|duration| i64::try_from(duration.as_nanos()).ok());
+  match nanos {
+    Some(nanos) => Ok((remaining, if sign == '-' { -nanos } else { nanos })),
+    None => Err(nom::Err::Error(nom::error::Error::new(
+      input,
+      nom::error::ErrorKind::Verify,
+    ))),
+  }
+}
+
 pub fn lex_timemsg(input: LocatedSpan) -> IResult<Token> {
   let (inp, _) = tag("@")(input.clone())?;
-  let (remaining, (seconds, fractional)) = parse_time_segment(inp)?;
+  if let Ok((remaining, offset)) = parse_relative_time(inp.clone()) {
+    return Ok((remaining, Token::RelativeTime(offset)));
+  }
+  let (remaining, (seconds, fractional)) = alt((parse_iso_time, parse_time_segment))(inp)?;
   let msg = TimeMsg {
     seconds,
     fractional,
@@ -547,7 +681,7 @@ fn get_err_msg(input: LocatedSpan) -> (Token, String) {
       ),
       '@' => (
         Token::Illegal(Box::new(Token::TimeMsg(TimeMsg::default()))),
-        "Invalid time msg: time format is `@<second>.<fractional>` eg. @2_208_988_800.2_123"
+        "Invalid time msg: time format is `@now`, `@now+250ms`, `@+1.5s`, `@2026-10-17T20:00:00.5Z` or `@<seconds>.<fraction>` eg. @2_208_988_800.25"
           .to_string(),
       ),
       '\'' => (
@@ -556,7 +690,7 @@ fn get_err_msg(input: LocatedSpan) -> (Token, String) {
       ),
       '#' => (
         Token::Illegal(Box::new(Token::Color(Color::default()))),
-        "Invalid color code: color format is `#<red><green><blue><alpha>` eg. #2F14DF2A"
+        "Invalid color code: color format is `#<red><green><blue><alpha>` eg. #2F14DF2A, bundles start with `#bundle`"
           .to_string(),
       ),
       '~' => (
@@ -593,6 +727,7 @@ fn lex_token(input: LocatedSpan) -> IResult<Token> {
     lex_blob,
     lex_timemsg,
     lex_midimsg,
+    bundle_keyword,
     lex_color,
     lex_long_integer,
     lex_double_float,
@@ -675,6 +810,119 @@ mod tests {
     }
   }
 
+  #[test]
+  fn test_bundle_tokens() {
+    let (tokens, errors) = Lexer::analyse(r#"#bundle @1.0 { /a 1; #bundle {/b "x";} }"#);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(
+      tokens,
+      vec![
+        Token::Bundle,
+        Token::TimeMsg(TimeMsg {
+          seconds: 1,
+          fractional: 0
+        }),
+        Token::LBrace,
+        Token::OSCPath("/a".to_string()),
+        Token::IntLiteral(1),
+        Token::Semicolon,
+        Token::Bundle,
+        Token::LBrace,
+        Token::OSCPath("/b".to_string()),
+        Token::StringLiteral("x".to_string()),
+        Token::Semicolon,
+        Token::RBrace,
+        Token::RBrace,
+        Token::EOF,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_addresses_in_braces() {
+    let cases = [
+      (
+        "{/a}",
+        vec![
+          Token::LBrace,
+          Token::OSCPath("/a".to_string()),
+          Token::RBrace,
+        ],
+      ),
+      (
+        "{/a/b;}",
+        vec![
+          Token::LBrace,
+          Token::OSCPath("/a/b".to_string()),
+          Token::Semicolon,
+          Token::RBrace,
+        ],
+      ),
+      (
+        "{/light/{1,2}/*}",
+        vec![
+          Token::LBrace,
+          Token::OSCPath("/light/{1,2}/*".to_string()),
+          Token::RBrace,
+        ],
+      ),
+    ];
+    for (input, expected) in cases.iter() {
+      let (tokens, errors) = Lexer::analyse(input);
+      assert!(errors.is_empty(), "{}: {:?}", input, errors);
+      let mut expected = expected.clone();
+      expected.push(Token::EOF);
+      assert_eq!(tokens, expected, "{}", input);
+    }
+  }
+
+  #[test]
+  fn test_time_literals() {
+    let ntp = |unix: u64, fractional: u32| {
+      Token::TimeMsg(TimeMsg {
+        seconds: (unix + time::NTP_UNIX_OFFSET) as u32,
+        fractional,
+      })
+    };
+    let valid = [
+      (
+        "@2_208_988_800.25",
+        Token::TimeMsg(TimeMsg {
+          seconds: 2_208_988_800,
+          fractional: 1 << 30,
+        }),
+      ),
+      ("@2026-10-17T20:00:00.5Z", ntp(1_792_267_200, 1 << 31)),
+      ("@2026-10-17T22:00:00+02:00", ntp(1_792_267_200, 0)),
+      ("@2026-10-17T17:30:00-02:30", ntp(1_792_267_200, 0)),
+      ("@now", Token::RelativeTime(0)),
+      ("@now+250ms", Token::RelativeTime(250_000_000)),
+      ("@now-1s", Token::RelativeTime(-1_000_000_000)),
+      ("@+1.5s", Token::RelativeTime(1_500_000_000)),
+      ("@+2", Token::RelativeTime(2_000_000_000)),
+    ];
+
+    for (input, expected) in valid.iter() {
+      let (tokens, errors) = Lexer::analyse(input);
+      assert!(errors.is_empty(), "{}: {:?}", input, errors);
+      assert_eq!(tokens, vec![expected.clone(), Token::EOF], "{}", input);
+    }
+
+    for input in [
+      "@2026-13-01T00:00:00Z",
+      "@2026-02-29T00:00:00Z",
+      "@now+1d",
+      "@now+99999999999999999999s",
+      "@+300000000h",
+      "@yesterday",
+    ]
+    .iter()
+    {
+      let (_, errors) = Lexer::analyse(input);
+      assert!(!errors.is_empty(), "{}", input);
+    }
+  }
+
   #[test]
   fn test_valid_blob() {
     let errors = RefCell::new(Vec::new());
@@ -1,19 +1,24 @@
 use nom::branch::*;
 use nom::bytes::complete::take;
-use nom::combinator::{map, verify};
+use nom::combinator::{map, opt, verify};
 use nom::error::{Error, ErrorKind};
-use nom::multi::many0;
+use nom::multi::{many0, many1, separated_list0};
 use nom::sequence::*;
 use nom::Err;
 use nom::*;
 use rosc::{OscArray, OscColor, OscMidiMessage, OscTime, OscType};
 
 use super::token::{Color, MidiMsg, TimeMsg, Token, Tokens};
+use crate::time;
 use std::result::Result::*;
+use std::time::{Duration, SystemTime};
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Stmt {
   ExprStmt(Expr),
+  /// `#bundle <timetag> { <element>; <element> }`, the timetag is optional and every element is
+  /// either a message (an OSC path followed by its arguments) or a single nested bundle.
+  BundleStmt(Option<Literal>, Vec<Program>),
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -37,6 +42,8 @@ pub enum Literal {
   Color(Color),
   MidiMsg(MidiMsg),
   TimeMsg(TimeMsg),
+  /// Nanoseconds from the moment the message is sent, resolved by `parse_message`.
+  RelativeTime(i64),
 }
 
 #[derive(PartialEq, Debug, Eq, Clone)]
@@ -65,7 +72,36 @@ fn parse_program(input: Tokens) -> IResult<Tokens, Program> {
 }
 
 fn parse_stmt(input: Tokens) -> IResult<Tokens, Stmt> {
-  parse_expr_stmt(input)
+  alt((parse_bundle_stmt, parse_expr_stmt))(input)
+}
+
+fn parse_bundle_stmt(input: Tokens) -> IResult<Tokens, Stmt> {
+  map(
+    preceded(
+      bundle_tag,
+      pair(
+        opt(parse_timetag),
+        delimited(
+          lbrace_tag,
+          terminated(
+            separated_list0(semicolon_tag, many1(parse_stmt)),
+            opt(semicolon_tag),
+          ),
+          rbrace_tag,
+        ),
+      ),
+    ),
+    |(timetag, elements)| Stmt::BundleStmt(timetag, elements),
+  )(input)
+}
+
+fn parse_timetag(input: Tokens) -> IResult<Tokens, Literal> {
+  let (i1, t1) = take(1usize)(input)?;
+  match t1.tok.first() {
+    Some(Token::TimeMsg(time)) => Ok((i1, Literal::TimeMsg(time.clone()))),
+    Some(Token::RelativeTime(offset)) => Ok((i1, Literal::RelativeTime(*offset))),
+    _ => Err(Err::Error(Error::new(input, ErrorKind::Tag))),
+  }
 }
 
 fn parse_expr_stmt(input: Tokens) -> IResult<Tokens, Stmt> {
@@ -90,6 +126,7 @@ fn parse_literal(input: Tokens) -> IResult<Tokens, Literal> {
       Token::Char(c) => Ok((i1, Literal::Char(c))),
       Token::MidiMessage(c) => Ok((i1, Literal::MidiMsg(c))),
       Token::TimeMsg(c) => Ok((i1, Literal::TimeMsg(c))),
+      Token::RelativeTime(c) => Ok((i1, Literal::RelativeTime(c))),
       _ => Err(Err::Error(Error::new(input, ErrorKind::Tag))),
     }
   }
@@ -111,6 +148,10 @@ fn parse_ident(input: Tokens) -> IResult<Tokens, Ident> {
 tag_token!(lbracket_tag, Token::LBracket);
 tag_token!(rbracket_tag, Token::RBracket);
 tag_token!(comma_tag, Token::Comma);
+tag_token!(lbrace_tag, Token::LBrace);
+tag_token!(rbrace_tag, Token::RBrace);
+tag_token!(semicolon_tag, Token::Semicolon);
+tag_token!(bundle_tag, Token::Bundle);
 tag_token!(eof_tag, Token::EOF);
 
 fn parse_lit_expr(input: Tokens) -> IResult<Tokens, Expr> {
@@ -216,18 +257,112 @@ fn parse_scalar(message: &Literal) -> OscType {
       data1: data1.to_owned(),
       data2: data2.to_owned(),
     }),
-    Literal::TimeMsg(TimeMsg {
-      seconds,
-      fractional,
-    }) => OscType::Time(OscTime {
-      seconds: seconds.to_owned(),
-      fractional: fractional.to_owned(),
-    }),
+    Literal::TimeMsg(time) => OscType::Time(parse_timetag_value(time)),
+    Literal::RelativeTime(offset) => OscType::Time(parse_relative_time(*offset)),
   }
 }
 
+/// Converts a bundle timetag, an omitted one means "immediately" as defined by OSC 1.0.
+pub fn parse_bundle_timetag(timetag: &Option<Literal>) -> OscTime {
+  match timetag {
+    Some(Literal::TimeMsg(time)) => parse_timetag_value(time),
+    Some(Literal::RelativeTime(offset)) => parse_relative_time(*offset),
+    _ => OscTime {
+      seconds: 0,
+      fractional: 1,
+    },
+  }
+}
+
+fn parse_timetag_value(time: &TimeMsg) -> OscTime {
+  OscTime {
+    seconds: time.seconds,
+    fractional: time.fractional,
+  }
+}
+
+fn parse_relative_time(offset: i64) -> OscTime {
+  let now = SystemTime::now();
+  let offset_abs = Duration::from_nanos(offset.unsigned_abs());
+  time::ntp_from_system(if offset < 0 {
+    now - offset_abs
+  } else {
+    now + offset_abs
+  })
+}
+
 fn parse_compound(message: &[Expr]) -> OscType {
   let arr = message.iter().map(parse_message).collect::<Vec<OscType>>();
   let aa = OscArray::from_iter(arr);
   OscType::Array(aa)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::super::lexer::Lexer;
+  use super::*;
+
+  fn parse(input: &str) -> Program {
+    let (tokens, errors) = Lexer::analyse(input);
+    assert!(errors.is_empty(), "{:?}", errors);
+    Parser::parse_tokens(Tokens::new(&tokens)).unwrap().1
+  }
+
+  fn path(addr: &str) -> Stmt {
+    Stmt::ExprStmt(Expr::Lit(Literal::OscPath(addr.to_string())))
+  }
+
+  fn int(val: i32) -> Stmt {
+    Stmt::ExprStmt(Expr::Lit(Literal::Int(val)))
+  }
+
+  #[test]
+  fn test_nested_bundles() {
+    let time = TimeMsg {
+      seconds: 2,
+      fractional: 1 << 31,
+    };
+    assert_eq!(
+      parse("#bundle @2.5 { /a 1 2; #bundle { /b 3 }; /c; }"),
+      vec![Stmt::BundleStmt(
+        Some(Literal::TimeMsg(time)),
+        vec![
+          vec![path("/a"), int(1), int(2)],
+          vec![Stmt::BundleStmt(None, vec![vec![path("/b"), int(3)]])],
+          vec![path("/c")],
+        ]
+      )]
+    );
+    assert_eq!(parse("#bundle {}"), vec![Stmt::BundleStmt(None, vec![])]);
+  }
+
+  #[test]
+  fn test_relative_timetags() {
+    let before = time::ntp_now();
+    let parsed = parse("#bundle @now+1s { /a @+250ms }");
+    let (timetag, message) = match parsed.as_slice() {
+      [Stmt::BundleStmt(timetag, elements)] => (parse_bundle_timetag(timetag), &elements[0]),
+      _ => panic!("expected a bundle, got {:?}", parsed),
+    };
+    assert!(timetag.seconds == before.seconds + 1 || timetag.seconds == before.seconds + 2);
+    match &message[1] {
+      Stmt::ExprStmt(expr) => match parse_message(expr) {
+        OscType::Time(time) => assert!(time.seconds - before.seconds <= 1),
+        other => panic!("expected a time, got {:?}", other),
+      },
+      other => panic!("expected an argument, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_invalid_bundles() {
+    for input in ["#bundle { /a 1", "#bundle /a 1 }", "#bundle { /a ;; /b }"].iter() {
+      let (tokens, _) = Lexer::analyse(input);
+      assert!(
+        Parser::parse_tokens(Tokens::new(&tokens)).is_err(),
+        "{}",
+        input
+      );
+    }
+  }
+}
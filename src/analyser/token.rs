@@ -10,8 +10,13 @@ pub enum Token {
   Comma,
   LBracket,
   RBracket,
+  LBrace,
+  RBrace,
+  Semicolon,
   Ident(String),
 
+  Bundle,
+
   OSCPath(String),
   StringLiteral(String),
   IntLiteral(i32),
@@ -21,6 +26,8 @@ pub enum Token {
   BoolLiteral(bool),
   Char(char),
   TimeMsg(TimeMsg),
+  /// A time relative to the moment of sending, in nanoseconds, eg. `@now+250ms`.
+  RelativeTime(i64),
   MidiMessage(MidiMsg),
   Color(Color),
   Blob(Vec<u8>),
@@ -150,8 +157,13 @@ impl fmt::Display for Token {
       Token::Comma => "Comma",
       Token::LBracket => "LBracket",
       Token::RBracket => "RBracket",
+      Token::LBrace => "LBrace",
+      Token::RBrace => "RBrace",
+      Token::Semicolon => "Semicolon",
       Token::Ident(_) => "Ident",
 
+      Token::Bundle => "Bundle",
+
       Token::OSCPath(_) => "OSCPath",
       Token::StringLiteral(_) => "StringLiteral",
       Token::IntLiteral(_) => "IntLiteral",
@@ -161,6 +173,7 @@ impl fmt::Display for Token {
       Token::BoolLiteral(_) => "BoolLiteral",
       Token::Char(_) => "Char",
       Token::TimeMsg(_) => "TimeMsg",
+      Token::RelativeTime(_) => "RelativeTime",
       Token::MidiMessage(_) => "MidiMessage",
       Token::Color(_) => "Color",
       Token::Blob(_) => "Blob",
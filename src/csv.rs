@@ -0,0 +1,410 @@
+//! Exports the messages shown by the monitor as CSV, eg. to analyse sensor data in a spreadsheet.
+//!
+//! Messages are either written to one file per address, named after it (`/sensor/1` goes to
+//! `sensor.1.csv`), with a column per argument inferred from the first message of the address:
+//!
+//! ```text
+//! timestamp,address,arg[0],arg[1][0],arg[1][1]
+//! 1792267200.123456,/sensor/1,0.5,12,true
+//! ```
+//!
+//! or to a single file in long format, with a row per argument:
+//!
+//! ```text
+//! timestamp,address,arg,value
+//! 1792267200.123456,/sensor/1,arg[0],0.5
+//! 1792267200.123456,/sensor/1,arg[1][0],12
+//! ```
+//!
+//! - `timestamp` is the time the message was received, in seconds since the Unix epoch,
+//! - arrays are flattened, `arg[1][0]` being the first element of the array passed second,
+//! - the messages of a bundle are exported one by one, with the time the bundle was received,
+//! - messages holding fewer values than the columns of their address leave the last cells empty,
+//!   the values in excess are left out,
+//! - strings are only quoted when they have to be, blobs are hex encoded, timetags written as
+//!   ISO-8601 UTC dates, colours and MIDI messages as in the send mode, and nil is left empty.
+//!
+//! Files are created (or truncated) when the monitor starts, or when the first message of their
+//! address is received, and every row is flushed as soon as it is written. At most 128 per-address
+//! files are kept open at once, the least recently written one is closed to make room for another
+//! and reopened to append to it when its address comes back.
+
+use super::hex;
+use super::osc::{Message, Packet, Type};
+use super::render;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where and how messages are exported.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Layout {
+  /// One file per address, in the given directory.
+  PerAddress(PathBuf),
+  /// A single file in long format.
+  Long(PathBuf),
+}
+
+/// Appends the messages it is given to CSV files.
+pub struct Exporter {
+  files: Files,
+}
+
+enum Files {
+  PerAddress(PathBuf, AddressFiles),
+  Long(BufWriter<File>),
+}
+
+impl Exporter {
+  /// Creates the directory of the per-address files, or the long format file.
+  pub fn create(layout: Layout) -> Result<Exporter, io::Error> {
+    let files = match layout {
+      Layout::PerAddress(dir) => {
+        fs::create_dir_all(&dir)?;
+        Files::PerAddress(dir, AddressFiles::default())
+      }
+      Layout::Long(path) => {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "timestamp,address,arg,value")?;
+        writer.flush()?;
+        Files::Long(writer)
+      }
+    };
+    Ok(Exporter { files })
+  }
+
+  /// Writes the messages of `packet` and flushes them to disk.
+  pub fn export(&mut self, packet: &Packet, received: SystemTime) -> Result<(), io::Error> {
+    let timestamp = timestamp(received);
+    for msg in packet.clone().into_msgs() {
+      match &mut self.files {
+        Files::PerAddress(dir, files) => write_row(dir, files, &timestamp, &msg)?,
+        Files::Long(writer) => write_long(writer, &timestamp, &msg)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+// How many per-address files may be open at once, well below the usual limit of 1024 open files
+// per process.
+const MAX_OPEN_FILES: usize = 128;
+
+// The file of every address received so far, by address.
+#[derive(Default)]
+struct AddressFiles {
+  files: HashMap<String, AddressFile>,
+  // Counts the rows written, to tell which open file was written least recently.
+  rows: u64,
+}
+
+struct AddressFile {
+  name: String,
+  columns: usize,
+  // `None` once closed to make room for another file.
+  writer: Option<BufWriter<File>>,
+  // The row last written to the file.
+  row: u64,
+}
+
+impl AddressFiles {
+  // The writer of the file of `addr` and its number of columns, reopening the file to append to
+  // it if it was closed, and closing the least recently written file if too many are open.
+  fn open(&mut self, dir: &Path, addr: &str) -> io::Result<(&mut BufWriter<File>, usize)> {
+    self.rows += 1;
+    let closed = self
+      .files
+      .get(addr)
+      .is_some_and(|file| file.writer.is_none());
+    let open = self.files.values().filter(|file| file.writer.is_some());
+    if closed && open.count() >= MAX_OPEN_FILES {
+      let least_recent = self
+        .files
+        .values_mut()
+        .filter(|file| file.writer.is_some())
+        .min_by_key(|file| file.row);
+      if let Some(file) = least_recent {
+        // Rows are flushed as they are written, there is nothing left to write.
+        file.writer = None;
+      }
+    }
+    let file = match self.files.get_mut(addr) {
+      Some(file) => file,
+      None => return Err(io::ErrorKind::NotFound.into()),
+    };
+    file.row = self.rows;
+    let writer = match file.writer.take() {
+      Some(writer) => writer,
+      None => BufWriter::new(OpenOptions::new().append(true).open(dir.join(&file.name))?),
+    };
+    let columns = file.columns;
+    Ok((file.writer.insert(writer), columns))
+  }
+}
+
+fn write_row(
+  dir: &Path,
+  files: &mut AddressFiles,
+  timestamp: &str,
+  msg: &Message,
+) -> Result<(), io::Error> {
+  let cells = flatten(&msg.args);
+  let new = !files.files.contains_key(&msg.addr);
+  if new {
+    let name = file_name(files, &msg.addr);
+    File::create(dir.join(&name))?;
+    let file = AddressFile {
+      name,
+      columns: cells.len(),
+      writer: None,
+      row: 0,
+    };
+    files.files.insert(msg.addr.clone(), file);
+  }
+  let (writer, columns) = files.open(dir, &msg.addr)?;
+  if new {
+    let header = cells.iter().map(|(label, _)| field(label));
+    let header = ["timestamp".to_string(), "address".to_string()]
+      .into_iter()
+      .chain(header);
+    writeln!(writer, "{}", header.collect::<Vec<String>>().join(","))?;
+  }
+  let mut row = vec![timestamp.to_string(), field(&msg.addr)];
+  row.extend((0..columns).map(|i| match cells.get(i) {
+    Some((_, arg)) => field(&value(arg)),
+    None => String::new(),
+  }));
+  writeln!(writer, "{}", row.join(","))?;
+  writer.flush()
+}
+
+// A file name made of the parts of `addr`, eg. `sensor.1.csv` for `/sensor/1`, which no other
+// address got already.
+fn file_name(files: &AddressFiles, addr: &str) -> String {
+  let stem = addr
+    .trim_start_matches('/')
+    .chars()
+    .map(|c| match c {
+      '/' => '.',
+      c if c.is_alphanumeric() || c == '-' || c == '_' => c,
+      _ => '_',
+    })
+    .collect::<String>();
+  let stem = match stem.is_empty() {
+    true => "_".to_string(),
+    false => stem,
+  };
+  let taken = |name: &str| files.files.values().any(|file| file.name == name);
+  let mut name = format!("{}.csv", stem);
+  let mut n = 1;
+  while taken(&name) {
+    n += 1;
+    name = format!("{}-{}.csv", stem, n);
+  }
+  name
+}
+
+fn write_long(writer: &mut BufWriter<File>, timestamp: &str, msg: &Message) -> io::Result<()> {
+  let cells = flatten(&msg.args);
+  if cells.is_empty() {
+    writeln!(writer, "{},{},,", timestamp, field(&msg.addr))?;
+  }
+  for (label, arg) in cells {
+    writeln!(
+      writer,
+      "{},{},{},{}",
+      timestamp,
+      field(&msg.addr),
+      field(&label),
+      field(&value(arg))
+    )?;
+  }
+  writer.flush()
+}
+
+/// The arguments of a message along with their column labels, eg. `arg[0]`, arrays flattened.
+pub fn flatten(args: &[Type]) -> Vec<(String, &Type)> {
+  let mut cells = vec![];
+  flatten_into(args, "arg", &mut cells);
+  cells
+}
+
+fn flatten_into<'a>(args: &'a [Type], prefix: &str, cells: &mut Vec<(String, &'a Type)>) {
+  for (i, arg) in args.iter().enumerate() {
+    let label = format!("{}[{}]", prefix, i);
+    match arg {
+      Type::Array(array) => flatten_into(&array.content, &label, cells),
+      _ => cells.push((label, arg)),
+    }
+  }
+}
+
+// The value of a cell, before quoting.
+fn value(arg: &Type) -> String {
+  match arg {
+    Type::Int(val) => val.to_string(),
+    Type::Long(val) => val.to_string(),
+    Type::Float(val) => val.to_string(),
+    Type::Double(val) => val.to_string(),
+    Type::String(val) => val.clone(),
+    Type::Char(val) => val.to_string(),
+    Type::Bool(val) => val.to_string(),
+    Type::Blob(val) => hex::encode(val),
+    Type::Time(_) => render::literal(arg).trim_start_matches('@').to_string(),
+    Type::Nil => String::new(),
+    Type::Color(_) | Type::Midi(_) | Type::Array(_) | Type::Inf => render::literal(arg),
+  }
+}
+
+// Quotes a field if it holds a comma, a quote or a line break (RFC 4180).
+fn field(value: &str) -> String {
+  match value.contains([',', '"', '\n', '\r']) {
+    true => format!("\"{}\"", value.replace('"', "\"\"")),
+    false => value.to_string(),
+  }
+}
+
+// Seconds since the Unix epoch, to the microsecond.
+fn timestamp(time: SystemTime) -> String {
+  let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+  format!("{}.{:06}", since.as_secs(), since.subsec_micros())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::osc::rosc::OscArray;
+  use super::super::osc::{msg, Bundle};
+  use super::super::time;
+  use super::*;
+  use std::time::Duration;
+
+  #[test]
+  fn test_cells() {
+    let args = vec![
+      Type::Float(0.5),
+      Type::Array(OscArray {
+        content: vec![Type::Int(12), Type::Bool(true)],
+      }),
+      Type::String("a \"b\", c".into()),
+      Type::Blob(vec![0, 255]),
+      Type::Nil,
+    ];
+    let cells = flatten(&args)
+      .into_iter()
+      .map(|(label, arg)| (label, field(&value(arg))))
+      .collect::<Vec<(String, String)>>();
+
+    assert_eq!(
+      cells,
+      [
+        ("arg[0]", "0.5"),
+        ("arg[1][0]", "12"),
+        ("arg[1][1]", "true"),
+        ("arg[2]", "\"a \"\"b\"\", c\""),
+        ("arg[3]", "00ff"),
+        ("arg[4]", ""),
+      ]
+      .iter()
+      .map(|(label, value)| (label.to_string(), value.to_string()))
+      .collect::<Vec<(String, String)>>()
+    );
+  }
+
+  #[test]
+  fn test_exports() {
+    let dir = std::env::temp_dir().join(format!("oscd-csv-{}", std::process::id()));
+    let received = UNIX_EPOCH + Duration::from_micros(1_792_267_200_123_456);
+    let packets = [
+      Packet::from(msg("/sensor/1", vec![Type::Float(0.5), Type::Int(12)])),
+      Packet::Bundle(Bundle {
+        timetag: time::ntp_now(),
+        content: vec![
+          Packet::from(msg("/sensor/1", vec![Type::Float(0.25)])).into(),
+          Packet::from(msg("/sensor_1", vec![])).into(),
+        ],
+      }),
+      Packet::from(msg(
+        "/sensor/1",
+        vec![Type::Float(1.0), Type::Int(3), Type::Int(4)],
+      )),
+      Packet::from(msg("/sensor.1", vec![Type::Char('x')])),
+    ];
+
+    let mut per_address = Exporter::create(Layout::PerAddress(dir.clone())).unwrap();
+    let mut long = Exporter::create(Layout::Long(dir.join("long.csv"))).unwrap();
+    for packet in packets.iter() {
+      per_address.export(packet, received).unwrap();
+      long.export(packet, received).unwrap();
+    }
+    let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+    let (sensor, underscore, dot, long) = (
+      read("sensor.1.csv"),
+      read("sensor_1.csv"),
+      read("sensor_1-2.csv"),
+      read("long.csv"),
+    );
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+      sensor,
+      "timestamp,address,arg[0],arg[1]\n\
+       1792267200.123456,/sensor/1,0.5,12\n\
+       1792267200.123456,/sensor/1,0.25,\n\
+       1792267200.123456,/sensor/1,1,3\n"
+    );
+    assert_eq!(
+      underscore,
+      "timestamp,address\n1792267200.123456,/sensor_1\n"
+    );
+    // `/sensor.1` would be written to the file of `/sensor_1` otherwise.
+    assert_eq!(
+      dot,
+      "timestamp,address,arg[0]\n1792267200.123456,/sensor.1,x\n"
+    );
+    assert_eq!(
+      long,
+      "timestamp,address,arg,value\n\
+       1792267200.123456,/sensor/1,arg[0],0.5\n\
+       1792267200.123456,/sensor/1,arg[1],12\n\
+       1792267200.123456,/sensor/1,arg[0],0.25\n\
+       1792267200.123456,/sensor_1,,\n\
+       1792267200.123456,/sensor/1,arg[0],1\n\
+       1792267200.123456,/sensor/1,arg[1],3\n\
+       1792267200.123456,/sensor/1,arg[2],4\n\
+       1792267200.123456,/sensor.1,arg[0],x\n"
+    );
+  }
+
+  #[test]
+  fn test_open_files() {
+    let dir = std::env::temp_dir().join(format!("oscd-csv-open-{}", std::process::id()));
+    let received = UNIX_EPOCH + Duration::from_secs(1);
+    let mut exporter = Exporter::create(Layout::PerAddress(dir.clone())).unwrap();
+    let addresses = (0..MAX_OPEN_FILES + 2)
+      .map(|i| format!("/fader/{}", i))
+      .collect::<Vec<String>>();
+    // The first addresses are closed to make room for the last ones, then reopened.
+    for addr in addresses.iter().chain(addresses.iter()) {
+      let packet = Packet::from(msg(addr, vec![Type::Int(1)]));
+      exporter.export(&packet, received).unwrap();
+    }
+    let open = match &exporter.files {
+      Files::PerAddress(_, files) => files
+        .files
+        .values()
+        .filter(|file| file.writer.is_some())
+        .count(),
+      Files::Long(_) => 0,
+    };
+    let first = fs::read_to_string(dir.join("fader.0.csv")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(open, MAX_OPEN_FILES);
+    assert_eq!(
+      first,
+      "timestamp,address,arg[0]\n1.000000,/fader/0,1\n1.000000,/fader/0,1\n"
+    );
+  }
+}
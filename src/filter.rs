@@ -0,0 +1,410 @@
+//! Decides which received messages the monitor displays.
+
+use std::cmp::Ordering;
+use std::net::{IpAddr, SocketAddr};
+
+use super::analyser::lexer::Lexer;
+use super::analyser::parser::{parse_message, Parser, Stmt};
+use super::analyser::token::Tokens;
+use super::osc::address::Pattern;
+use super::osc::rosc::OscPacket;
+use super::osc::{Bundle, Message, Packet, Type};
+use super::render;
+
+/// Keeps the packets sent from any of the `sources` (or from anywhere when there are none),
+/// and within them the messages whose address matches any of the `include` patterns (or all of
+/// them when there are none), none of the `exclude` patterns and all of the `predicates`.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+  include: Vec<Pattern>,
+  exclude: Vec<Pattern>,
+  sources: Vec<Source>,
+  predicates: Vec<Predicate>,
+}
+
+/// Restricts the sender of a packet, by IP address, by socket address or by CIDR range.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+  Addr(SocketAddr),
+  Ip(IpAddr),
+  Cidr(IpAddr, u8),
+}
+
+/// A condition on the arguments of a message, eg. `arg[1] > 0.5`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+  /// The type tag string equals the given one, eg. `tags == ,ifs`.
+  Tags(Op, String),
+  /// The number of arguments compares to the given count, eg. `argc >= 2`.
+  Argc(Op, usize),
+  /// The argument at the given index compares to the given value, eg. `arg[0] == "default"`.
+  Arg(usize, Op, Type),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+impl Filter {
+  pub fn new(
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    sources: Vec<Source>,
+    predicates: Vec<Predicate>,
+  ) -> Self {
+    Self {
+      include,
+      exclude,
+      sources,
+      predicates,
+    }
+  }
+
+  /// Returns whether the filter lets every packet through untouched.
+  pub fn is_empty(&self) -> bool {
+    self.include.is_empty()
+      && self.exclude.is_empty()
+      && self.sources.is_empty()
+      && self.predicates.is_empty()
+  }
+
+  /// Returns whether the given message should be displayed.
+  pub fn matches(&self, msg: &Message) -> bool {
+    (self.include.is_empty() || self.include.iter().any(|p| p.matches(&msg.addr)))
+      && !self.exclude.iter().any(|p| p.matches(&msg.addr))
+      && self.predicates.iter().all(|p| p.matches(msg))
+  }
+
+  /// Returns the part of the packet that should be displayed, if any.
+  ///
+  /// Bundles are pruned down to the messages that match, nested bundles left empty by the
+  /// pruning are dropped altogether.
+  pub fn apply(&self, packet: Packet, source: SocketAddr) -> Option<Packet> {
+    if !self.sources.is_empty() && !self.sources.iter().any(|s| s.matches(source)) {
+      return None;
+    }
+    match packet {
+      Packet::Message(msg) => self.matches(&msg).then_some(Packet::Message(msg)),
+      Packet::Bundle(bundle) => self.apply_bundle(bundle).map(Packet::Bundle),
+    }
+  }
+
+  fn apply_bundle(&self, bundle: Bundle) -> Option<Bundle> {
+    let content = bundle
+      .content
+      .into_iter()
+      .filter_map(|packet| match packet {
+        OscPacket::Message(msg) => self.matches(&msg).then_some(OscPacket::Message(msg)),
+        OscPacket::Bundle(bundle) => self.apply_bundle(bundle).map(OscPacket::Bundle),
+      })
+      .collect::<Vec<OscPacket>>();
+    if content.is_empty() {
+      None
+    } else {
+      Some(Bundle {
+        timetag: bundle.timetag,
+        content,
+      })
+    }
+  }
+}
+
+impl Source {
+  /// Parses `10.0.0.5`, `10.0.0.5:9000`, `[fe80::1]:9000` or a CIDR range like `10.0.0.0/24`.
+  pub fn parse(source: &str) -> Result<Source, String> {
+    let invalid = || {
+      format!(
+        "`{}` is not an IP address, socket address or CIDR range",
+        source
+      )
+    };
+    if let Some((ip, prefix)) = source.split_once('/') {
+      let ip = ip.parse::<IpAddr>().map_err(|_| invalid())?;
+      let max = if ip.is_ipv4() { 32 } else { 128 };
+      return match prefix.parse::<u8>() {
+        Ok(prefix) if prefix <= max => Ok(Source::Cidr(ip, prefix)),
+        _ => Err(format!("`{}` prefix length should be 0 to {}", source, max)),
+      };
+    }
+    if let Ok(ip) = source.parse::<IpAddr>() {
+      return Ok(Source::Ip(ip));
+    }
+    source
+      .parse::<SocketAddr>()
+      .map(Source::Addr)
+      .map_err(|_| invalid())
+  }
+
+  pub fn matches(&self, source: SocketAddr) -> bool {
+    let ip = canonical(source.ip());
+    match self {
+      Source::Addr(addr) => canonical(addr.ip()) == ip && addr.port() == source.port(),
+      Source::Ip(addr) => canonical(*addr) == ip,
+      Source::Cidr(net, prefix) => match (canonical(*net), ip) {
+        (IpAddr::V4(net), IpAddr::V4(ip)) => {
+          let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+          u32::from(net) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(net), IpAddr::V6(ip)) => {
+          let mask = u128::MAX.checked_shl(128 - *prefix as u32).unwrap_or(0);
+          u128::from(net) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+      },
+    }
+  }
+}
+
+// IPv4 senders seen through a dual-stack socket show up as IPv4-mapped IPv6 addresses.
+fn canonical(ip: IpAddr) -> IpAddr {
+  match ip {
+    IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+    ip => ip,
+  }
+}
+
+impl Predicate {
+  /// Parses `tags == ,ifs`, `argc >= 2` or `arg[<index>] <op> <value>` where `<value>` is written
+  /// in the same syntax as the send mode arguments, eg. `arg[0] == "default"`.
+  pub fn parse(predicate: &str) -> Result<Predicate, String> {
+    let ops = [
+      ("==", Op::Eq),
+      ("!=", Op::Ne),
+      ("<=", Op::Le),
+      (">=", Op::Ge),
+      ("<", Op::Lt),
+      (">", Op::Gt),
+    ];
+    let (pos, token, op) = ops
+      .iter()
+      .filter_map(|(token, op)| predicate.find(token).map(|pos| (pos, *token, *op)))
+      .min_by_key(|(pos, token, _)| (*pos, usize::MAX - token.len()))
+      .ok_or_else(|| format!("`{}` is missing a comparison, eg. arg[1] > 0.5", predicate))?;
+    let subject = predicate[..pos].trim();
+    let value = predicate[pos + token.len()..].trim();
+
+    match subject {
+      "tags" => {
+        let tags = value.trim_matches('"');
+        let tags = if tags.starts_with(',') {
+          tags.to_string()
+        } else {
+          format!(",{}", tags)
+        };
+        Ok(Predicate::Tags(op, tags))
+      }
+      "argc" => value
+        .parse::<usize>()
+        .map(|count| Predicate::Argc(op, count))
+        .map_err(|_| format!("`{}` argc should be compared to a number", predicate)),
+      _ => {
+        let index = subject
+          .strip_prefix("arg[")
+          .and_then(|rest| rest.strip_suffix(']'))
+          .and_then(|index| index.trim().parse::<usize>().ok())
+          .ok_or_else(|| {
+            format!(
+              "`{}` should compare tags, argc or arg[<index>], eg. arg[0] == \"default\"",
+              predicate
+            )
+          })?;
+        let value = literal(value)
+          .ok_or_else(|| format!("`{}` has an invalid value `{}`", predicate, value))?;
+        Ok(Predicate::Arg(index, op, value))
+      }
+    }
+  }
+
+  pub fn matches(&self, msg: &Message) -> bool {
+    match self {
+      Predicate::Tags(op, tags) => op.test(render::type_tags(&msg.args).cmp(tags)),
+      Predicate::Argc(op, count) => op.test(msg.args.len().cmp(count)),
+      Predicate::Arg(index, op, value) => match msg.args.get(*index) {
+        Some(arg) => match compare(arg, value) {
+          Some(ordering) => op.test(ordering),
+          None => *op == Op::Ne,
+        },
+        None => false,
+      },
+    }
+  }
+}
+
+impl Op {
+  fn test(&self, ordering: Ordering) -> bool {
+    match self {
+      Op::Eq => ordering == Ordering::Equal,
+      Op::Ne => ordering != Ordering::Equal,
+      Op::Lt => ordering == Ordering::Less,
+      Op::Le => ordering != Ordering::Greater,
+      Op::Gt => ordering == Ordering::Greater,
+      Op::Ge => ordering != Ordering::Less,
+    }
+  }
+}
+
+// Lexes and parses a single send mode argument, eg. `0.5` or `"default"`.
+fn literal(value: &str) -> Option<Type> {
+  let (tokens, lex_error) = Lexer::analyse(value);
+  if !lex_error.is_empty() {
+    return None;
+  }
+  match Parser::parse_tokens(Tokens::new(&tokens)) {
+    Ok((_, stmts)) => match stmts.as_slice() {
+      [Stmt::ExprStmt(expr)] => Some(parse_message(expr)),
+      _ => None,
+    },
+    Err(_) => None,
+  }
+}
+
+// Numbers compare by value whatever their OSC type, strings compare alphabetically and any other
+// argument can only be equal to the same value. `None` means the two can't be compared.
+fn compare(arg: &Type, value: &Type) -> Option<Ordering> {
+  match (number(arg), number(value)) {
+    (Some(arg), Some(value)) => return arg.partial_cmp(&value),
+    (Some(_), None) | (None, Some(_)) => return None,
+    (None, None) => {}
+  }
+  match (arg, value) {
+    (Type::String(arg), Type::String(value)) => Some(arg.cmp(value)),
+    _ => (render::literal(arg) == render::literal(value)).then_some(Ordering::Equal),
+  }
+}
+
+fn number(arg: &Type) -> Option<f64> {
+  match arg {
+    Type::Int(val) => Some(*val as f64),
+    Type::Long(val) => Some(*val as f64),
+    Type::Float(val) => Some(*val as f64),
+    Type::Double(val) => Some(*val),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::osc::rosc::OscTime;
+  use super::*;
+
+  fn msg(addr: &str) -> OscPacket {
+    OscPacket::Message(Message {
+      addr: addr.to_string(),
+      args: vec![],
+    })
+  }
+
+  #[test]
+  fn test_prune_bundle() {
+    let source = "127.0.0.1:57120".parse().unwrap();
+    let timetag = OscTime {
+      seconds: 0,
+      fractional: 1,
+    };
+    let filter = Filter::new(
+      vec![Pattern::parse("/light/*").unwrap()],
+      vec![Pattern::parse("/light/debug").unwrap()],
+      vec![],
+      vec![],
+    );
+    let packet = Packet::Bundle(Bundle {
+      timetag,
+      content: vec![
+        msg("/light/1"),
+        msg("/light/debug"),
+        OscPacket::Bundle(Bundle {
+          timetag,
+          content: vec![msg("/audio/1")],
+        }),
+      ],
+    });
+
+    assert_eq!(
+      filter.apply(packet, source).map(Packet::into_msgs),
+      Some(vec![Message {
+        addr: "/light/1".to_string(),
+        args: vec![],
+      }])
+    );
+    assert_eq!(
+      filter.apply(Packet::from(("/light/debug", vec![])), source),
+      None
+    );
+  }
+
+  #[test]
+  fn test_sources() {
+    let sources = [
+      ("10.0.0.5", "10.0.0.5:9000", true),
+      ("10.0.0.5", "10.0.0.6:9000", false),
+      ("10.0.0.5:9000", "10.0.0.5:9000", true),
+      ("10.0.0.5:9000", "10.0.0.5:9001", false),
+      ("10.0.0.0/24", "10.0.0.200:1", true),
+      ("10.0.0.0/24", "10.0.1.1:1", false),
+      ("0.0.0.0/0", "192.168.1.1:1", true),
+      ("10.0.0.0/8", "[::ffff:10.1.2.3]:1", true),
+      ("fe80::/10", "[fe80::1]:1", true),
+      ("fe80::/10", "[2001:db8::1]:1", false),
+    ];
+
+    for (source, addr, expected) in sources.iter() {
+      assert_eq!(
+        Source::parse(source)
+          .unwrap()
+          .matches(addr.parse().unwrap()),
+        *expected,
+        "{} {}",
+        source,
+        addr
+      );
+    }
+    for source in ["10.0.0.0/33", "localhost", "10.0.0"].iter() {
+      assert!(Source::parse(source).is_err(), "{}", source);
+    }
+  }
+
+  #[test]
+  fn test_predicates() {
+    let msg = Message {
+      addr: "/s_new".to_string(),
+      args: vec![
+        Type::String("default".to_string()),
+        Type::Float(0.75),
+        Type::Int(2),
+      ],
+    };
+    let predicates = [
+      ("tags == ,sfi", true),
+      ("tags == sfi", true),
+      ("tags != ,sfi", false),
+      ("argc == 3", true),
+      ("argc > 3", false),
+      ("arg[0] == \"default\"", true),
+      ("arg[0] != \"default\"", false),
+      ("arg[1] > 0.5", true),
+      ("arg[1]<=0.5", false),
+      ("arg[2] >= 2.0_f64", true),
+      ("arg[2] == true", false),
+      ("arg[2] != true", true),
+      ("arg[5] == 1", false),
+    ];
+
+    for (predicate, expected) in predicates.iter() {
+      assert_eq!(
+        Predicate::parse(predicate).unwrap().matches(&msg),
+        *expected,
+        "{}",
+        predicate
+      );
+    }
+    for predicate in ["arg[0]", "args == 1", "arg[x] == 1", "arg[0] == default"].iter() {
+      assert!(Predicate::parse(predicate).is_err(), "{}", predicate);
+    }
+  }
+}
@@ -0,0 +1,43 @@
+//! Bytes as lowercase hex digits, two per byte, as blobs and raw datagrams are written in the
+//! monitor's outputs and session files.
+
+use std::fmt::Write;
+
+/// Encodes bytes as two lowercase hex digits each, eg. `"00ff"` for `[0, 255]`.
+pub fn encode(bytes: &[u8]) -> String {
+  let mut hex = String::with_capacity(bytes.len() * 2);
+  for byte in bytes {
+    write!(hex, "{:02x}", byte).unwrap();
+  }
+  hex
+}
+
+/// Decodes two hex digits, of either case, per byte. Unlike `u8::from_str_radix`, a sign as in
+/// `"+f"` is not a digit.
+pub fn decode(hex: &str) -> Option<Vec<u8>> {
+  let digit = |c: &u8| (*c as char).to_digit(16);
+  hex
+    .as_bytes()
+    .chunks(2)
+    .map(|pair| match pair {
+      [high, low] => Some((digit(high)? << 4 | digit(low)?) as u8),
+      _ => None,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_hex() {
+    assert_eq!(encode(&[]), "");
+    assert_eq!(encode(&[0, 10, 255]), "000aff");
+    assert_eq!(decode("000aFF"), Some(vec![0, 10, 255]));
+    assert_eq!(decode(""), Some(vec![]));
+    for invalid in ["f", "fff", "zz", "+f", "-1", "é"] {
+      assert_eq!(decode(invalid), None, "{}", invalid);
+    }
+  }
+}
@@ -0,0 +1,558 @@
+//! Packets as JSON, one object per line (JSON Lines), for tools such as `jq`, and back.
+//!
+//! ```text
+//! {"timestamp":"2026-10-17T20:00:00.123456Z","source":"127.0.0.1:57120","port":57110,"proto":"udp","size":24,"packet":{"type":"message","address":"/n_set","types":",if","args":[{"type":"int","value":1000},{"type":"float","value":0.5}]}}
+//! ```
+//!
+//! - `timestamp` is the time the packet was received, `port` and `proto` the local port it
+//!   arrived on, `size` its length in bytes,
+//! - `packet` is either a message, `{"type":"message","address":..,"types":..,"args":[..]}`, or a
+//!   bundle, `{"type":"bundle","timetag":..,"elements":[..]}` whose elements are packets too,
+//! - every argument is `{"type":..,"value":..}`, the type being one of `int`, `long`, `float`,
+//!   `double`, `string`, `char`, `bool`, `blob` (hex encoded), `time`, `color`, `midi`, `array`,
+//!   `nil` and `inf`, the last two without a value,
+//! - floats and doubles that are not finite are written as strings, `"NaN"`, `"inf"` or `"-inf"`,
+//! - timetags are `{"seconds":..,"fractional":..,"date":..}`, the raw NTP timestamp along with its
+//!   ISO-8601 UTC date (left out of the "immediately" timetag),
+//! - packets that could not be decoded come with `error`, `reason` and their hex encoded `bytes`
+//!   instead of `packet`.
+//!
+//! The send mode reads packets written the same way, alone or as a whole line of the monitor,
+//! and encodes them back byte for byte, but for NaNs: they all come back as the one `f32::NAN` or
+//! `f64::NAN`, whatever their sign and payload were. Hand written ones can be shorter:
+//!
+//! ```text
+//! {"address":"/n_set","args":[{"type":"i","value":1000},{"type":"f","value":0.5}]}
+//! {"timetag":{"seconds":3969216000},"elements":[{"address":"/n_free","args":[{"type":"i","value":1000}]}]}
+//! ```
+//!
+//! - arguments can be typed with their OSC type tag, eg. `f` for `float`, `T` and `F` need no value,
+//! - `type` can be left out of messages and bundles, `types` is ignored,
+//! - `args` can be left out of messages without arguments, `timetag` out of bundles to be
+//!   processed immediately and `fractional` out of timetags.
+
+use super::hex;
+use super::osc::rosc::{OscArray, OscPacket, OscTime};
+use super::osc::{Bundle, Color, Message, MidiMessage, Packet, Type};
+use super::ports::Proto;
+use super::render;
+use super::time;
+use serde_json::{json, Map, Number, Value};
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+/// Where and when a packet was received.
+pub struct Origin {
+  pub received: SystemTime,
+  pub source: SocketAddr,
+  /// The local port the packet arrived on.
+  pub port: u16,
+  pub proto: Proto,
+  pub size: usize,
+}
+
+impl Origin {
+  fn fields(&self) -> Map<String, Value> {
+    let mut fields = Map::new();
+    let timestamp = time::iso8601(time::ntp_from_system(self.received));
+    fields.insert("timestamp".into(), timestamp.into());
+    fields.insert("source".into(), self.source.to_string().into());
+    fields.insert("port".into(), self.port.into());
+    fields.insert("proto".into(), self.proto.to_string().into());
+    fields.insert("size".into(), self.size.into());
+    fields
+  }
+}
+
+/// The line of a received packet.
+pub fn received(origin: &Origin, packet: &Packet) -> Value {
+  let mut line = origin.fields();
+  line.insert("packet".into(), self::packet(packet));
+  Value::Object(line)
+}
+
+/// The line of a datagram that could not be decoded, `reason` telling where it broke.
+pub fn malformed(origin: &Origin, bytes: &[u8], error: String, reason: String) -> Value {
+  let mut line = origin.fields();
+  line.insert("error".into(), error.into());
+  line.insert("reason".into(), reason.into());
+  line.insert("bytes".into(), hex::encode(bytes).into());
+  Value::Object(line)
+}
+
+pub fn packet(packet: &Packet) -> Value {
+  match packet {
+    Packet::Message(msg) => message(msg),
+    Packet::Bundle(bundle) => self::bundle(bundle),
+  }
+}
+
+fn message(msg: &Message) -> Value {
+  json!({
+    "type": "message",
+    "address": msg.addr,
+    "types": render::type_tags(&msg.args),
+    "args": msg.args.iter().map(arg).collect::<Vec<Value>>(),
+  })
+}
+
+fn bundle(bundle: &Bundle) -> Value {
+  let elements = bundle.content.iter().map(|element| match element {
+    OscPacket::Message(msg) => message(msg),
+    OscPacket::Bundle(bundle) => self::bundle(bundle),
+  });
+  json!({
+    "type": "bundle",
+    "timetag": timetag(bundle.timetag),
+    "elements": elements.collect::<Vec<Value>>(),
+  })
+}
+
+pub fn arg(arg: &Type) -> Value {
+  let (name, value) = match arg {
+    Type::Int(val) => ("int", json!(val)),
+    Type::Long(val) => ("long", json!(val)),
+    Type::Float(val) => ("float", float(val)),
+    Type::Double(val) => ("double", float(val)),
+    Type::String(val) => ("string", json!(val)),
+    Type::Char(val) => ("char", json!(val)),
+    Type::Bool(val) => ("bool", json!(val)),
+    Type::Blob(val) => ("blob", json!(hex::encode(val))),
+    Type::Time(val) => ("time", timetag(*val)),
+    Type::Color(val) => (
+      "color",
+      json!({ "red": val.red, "green": val.green, "blue": val.blue, "alpha": val.alpha }),
+    ),
+    Type::Midi(val) => (
+      "midi",
+      json!({ "port": val.port, "status": val.status, "data1": val.data1, "data2": val.data2 }),
+    ),
+    Type::Array(val) => (
+      "array",
+      Value::Array(val.content.iter().map(self::arg).collect()),
+    ),
+    Type::Nil => return json!({ "type": "nil" }),
+    Type::Inf => return json!({ "type": "inf" }),
+  };
+  json!({ "type": name, "value": value })
+}
+
+pub fn timetag(timetag: OscTime) -> Value {
+  let mut fields = Map::new();
+  fields.insert("seconds".into(), timetag.seconds.into());
+  fields.insert("fractional".into(), timetag.fractional.into());
+  if timetag.seconds != 0 {
+    fields.insert("date".into(), time::iso8601(timetag).into());
+  }
+  Value::Object(fields)
+}
+
+// Written the shortest way that reads back as the same value, eg. `0.1` rather than the
+// `0.10000000149011612` a float becomes as a double. JSON has no numbers for NaN and infinities.
+fn float<F: ToString>(val: &F) -> Value {
+  let shortest = val.to_string();
+  match shortest.parse::<f64>().ok().and_then(Number::from_f64) {
+    Some(number) => Value::Number(number),
+    None => Value::String(shortest),
+  }
+}
+
+/// Reads a packet written as JSON, on its own or as a line of the monitor.
+///
+/// Syntax errors come with the column (1 for the first character) they were found at.
+pub fn parse(input: &str) -> Result<Packet, (Option<usize>, String)> {
+  let value = serde_json::from_str::<Value>(input).map_err(|e| {
+    let message = e.to_string();
+    // The position is given by the column already.
+    let message = match message.rsplit_once(" at line ") {
+      Some((message, _)) => message.to_string(),
+      None => message,
+    };
+    (Some(e.column()), message)
+  })?;
+  let packet = match value.get("packet") {
+    Some(packet) => packet,
+    None => &value,
+  };
+  read_packet(packet).map_err(|e| (None, e))
+}
+
+fn read_packet(value: &Value) -> Result<Packet, String> {
+  let kind = match value.get("type") {
+    Some(kind) => kind.as_str().unwrap_or_default(),
+    None if value.get("address").is_some() => "message",
+    None if value.get("elements").is_some() => "bundle",
+    None => "",
+  };
+  match kind {
+    "message" => read_message(value).map(Packet::Message),
+    "bundle" => read_bundle(value).map(Packet::Bundle),
+    _ => Err(format!(
+      "`{}` is neither a message, with an address, nor a bundle, with elements",
+      value
+    )),
+  }
+}
+
+fn read_message(value: &Value) -> Result<Message, String> {
+  let addr = match value.get("address") {
+    Some(Value::String(addr)) if addr.starts_with('/') => addr.clone(),
+    Some(addr) => return Err(format!("`{}` is not an OSC address, eg. \"/s_new\"", addr)),
+    None => return Err("a message needs an address, eg. \"address\":\"/s_new\"".to_string()),
+  };
+  let args = match value.get("args") {
+    Some(Value::Array(args)) => args
+      .iter()
+      .enumerate()
+      .map(|(i, arg)| read_arg(arg).map_err(|e| format!("{}, argument {} of {}", e, i + 1, addr)))
+      .collect::<Result<Vec<Type>, String>>()?,
+    Some(args) => return Err(format!("`{}` should be an array of arguments", args)),
+    None => vec![],
+  };
+  Ok(Message { addr, args })
+}
+
+fn read_bundle(value: &Value) -> Result<Bundle, String> {
+  let timetag = match value.get("timetag") {
+    Some(timetag) => read_timetag(timetag)?,
+    None => OscTime {
+      seconds: 0,
+      fractional: 1,
+    },
+  };
+  let content = match value.get("elements") {
+    Some(Value::Array(elements)) => elements
+      .iter()
+      .map(|element| read_packet(element).map(OscPacket::from))
+      .collect::<Result<Vec<OscPacket>, String>>()?,
+    Some(elements) => return Err(format!("`{}` should be an array of packets", elements)),
+    None => vec![],
+  };
+  Ok(Bundle { timetag, content })
+}
+
+fn read_arg(value: &Value) -> Result<Type, String> {
+  let kind = match value.get("type") {
+    Some(Value::String(kind)) => kind.as_str(),
+    _ => {
+      return Err(format!(
+        "`{}` should be written {{\"type\":..,\"value\":..}}",
+        value
+      ))
+    }
+  };
+  let val = match (kind, value.get("value")) {
+    ("nil" | "N", _) => return Ok(Type::Nil),
+    ("inf" | "I", _) => return Ok(Type::Inf),
+    ("T", None) => return Ok(Type::Bool(true)),
+    ("F", None) => return Ok(Type::Bool(false)),
+    (_, Some(val)) => val,
+    (_, None) => return Err(format!("a `{}` argument needs a value", kind)),
+  };
+  let invalid = |what: &str| format!("`{}` is not {}", val, what);
+  let arg = match kind {
+    "int" | "i" => Type::Int(
+      val
+        .as_i64()
+        .and_then(|val| i32::try_from(val).ok())
+        .ok_or_else(|| invalid("an int"))?,
+    ),
+    "long" | "h" => Type::Long(val.as_i64().ok_or_else(|| invalid("a long"))?),
+    "float" | "f" => Type::Float(read_float(val).ok_or_else(|| invalid("a float"))? as f32),
+    "double" | "d" => Type::Double(read_float(val).ok_or_else(|| invalid("a double"))?),
+    "string" | "s" | "S" => Type::String(val.as_str().ok_or_else(|| invalid("a string"))?.into()),
+    "char" | "c" => {
+      let mut chars = val.as_str().unwrap_or_default().chars();
+      match (chars.next(), chars.next()) {
+        (Some(c), None) => Type::Char(c),
+        _ => return Err(invalid("a single character")),
+      }
+    }
+    "bool" | "T" | "F" => Type::Bool(val.as_bool().ok_or_else(|| invalid("true or false"))?),
+    "blob" | "b" => Type::Blob(
+      val
+        .as_str()
+        .and_then(hex::decode)
+        .ok_or_else(|| invalid("hex encoded bytes, eg. \"00ff\""))?,
+    ),
+    "time" | "t" => Type::Time(read_timetag(val)?),
+    "color" | "r" => Type::Color(Color {
+      red: read_byte(val, "red")?,
+      green: read_byte(val, "green")?,
+      blue: read_byte(val, "blue")?,
+      alpha: read_byte(val, "alpha")?,
+    }),
+    "midi" | "m" => Type::Midi(MidiMessage {
+      port: read_byte(val, "port")?,
+      status: read_byte(val, "status")?,
+      data1: read_byte(val, "data1")?,
+      data2: read_byte(val, "data2")?,
+    }),
+    "array" => Type::Array(OscArray {
+      content: val
+        .as_array()
+        .ok_or_else(|| invalid("an array of arguments"))?
+        .iter()
+        .map(read_arg)
+        .collect::<Result<Vec<Type>, String>>()?,
+    }),
+    _ => {
+      return Err(format!(
+        "`{}` is not an OSC type, eg. int, float or string",
+        kind
+      ))
+    }
+  };
+  Ok(arg)
+}
+
+fn read_timetag(value: &Value) -> Result<OscTime, String> {
+  let field = |name: &str| match value.get(name) {
+    Some(field) => field.as_u64().and_then(|field| u32::try_from(field).ok()),
+    None => Some(0),
+  };
+  match (value.is_object(), field("seconds"), field("fractional")) {
+    (true, Some(seconds), Some(fractional)) => Ok(OscTime {
+      seconds,
+      fractional,
+    }),
+    _ => Err(format!(
+      "`{}` should be written {{\"seconds\":..,\"fractional\":..}}, both 32-bit",
+      value
+    )),
+  }
+}
+
+fn read_byte(value: &Value, name: &str) -> Result<u8, String> {
+  value
+    .get(name)
+    .and_then(Value::as_u64)
+    .and_then(|byte| u8::try_from(byte).ok())
+    .ok_or_else(|| format!("`{}` should have a `{}` from 0 to 255", value, name))
+}
+
+// Numbers, or `"NaN"`, `"inf"` and `"-inf"` for what JSON has no numbers for. Every NaN is read
+// as the canonical one.
+fn read_float(value: &Value) -> Option<f64> {
+  match value {
+    Value::Number(number) => number.as_f64(),
+    Value::String(val) => val.parse::<f64>().ok().filter(|val| !val.is_finite()),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::osc;
+  use super::super::osc::msg;
+  use super::*;
+
+  #[test]
+  fn test_messages() {
+    let packet = Packet::from(msg(
+      "/s_new",
+      vec![
+        Type::String("default".into()),
+        Type::Int(-1),
+        Type::Long(1 << 60),
+        Type::Float(0.1),
+        Type::Double(f64::NEG_INFINITY),
+        Type::Bool(true),
+        Type::Blob(vec![0, 255]),
+        Type::Color(Color {
+          red: 47,
+          green: 20,
+          blue: 223,
+          alpha: 42,
+        }),
+        Type::Array(OscArray {
+          content: vec![Type::Nil, Type::Char('x')],
+        }),
+        Type::Inf,
+      ],
+    ));
+
+    assert_eq!(
+      self::packet(&packet).to_string(),
+      concat!(
+        r#"{"type":"message","address":"/s_new","types":",sihfdTbr[Nc]I","args":["#,
+        r#"{"type":"string","value":"default"},"#,
+        r#"{"type":"int","value":-1},"#,
+        r#"{"type":"long","value":1152921504606846976},"#,
+        r#"{"type":"float","value":0.1},"#,
+        r#"{"type":"double","value":"-inf"},"#,
+        r#"{"type":"bool","value":true},"#,
+        r#"{"type":"blob","value":"00ff"},"#,
+        r#"{"type":"color","value":{"red":47,"green":20,"blue":223,"alpha":42}},"#,
+        r#"{"type":"array","value":[{"type":"nil"},{"type":"char","value":"x"}]},"#,
+        r#"{"type":"inf"}]}"#
+      )
+    );
+  }
+
+  #[test]
+  fn test_bundles() {
+    let bundle = Packet::Bundle(Bundle {
+      timetag: OscTime {
+        seconds: 3_000_000_000,
+        fractional: 1 << 31,
+      },
+      content: vec![
+        Packet::from(msg("/a", vec![])).into(),
+        OscPacket::Bundle(Bundle {
+          timetag: OscTime {
+            seconds: 0,
+            fractional: 1,
+          },
+          content: vec![],
+        }),
+      ],
+    });
+
+    assert_eq!(
+      self::packet(&bundle).to_string(),
+      concat!(
+        r#"{"type":"bundle","#,
+        r#""timetag":{"seconds":3000000000,"fractional":2147483648,"date":"1995-01-25T05:20:00.5Z"},"#,
+        r#""elements":[{"type":"message","address":"/a","types":",","args":[]},"#,
+        r#"{"type":"bundle","timetag":{"seconds":0,"fractional":1},"elements":[]}]}"#
+      )
+    );
+  }
+
+  #[test]
+  fn test_round_trips() {
+    let packets = vec![
+      Packet::from(msg(
+        "/everything",
+        vec![
+          Type::Int(i32::MIN),
+          Type::Long(i64::MAX),
+          Type::Float(0.1),
+          Type::Float(-3.4028235e38),
+          Type::Float(f32::INFINITY),
+          Type::Float(f32::NAN),
+          Type::Double(0.1),
+          Type::Double(f64::NAN),
+          Type::Double(5e-324),
+          Type::String("é \"quoted\"\n".into()),
+          Type::Char('€'),
+          Type::Bool(false),
+          Type::Blob(vec![]),
+          Type::Blob(vec![1, 2, 3]),
+          Type::Time(OscTime {
+            seconds: 3_969_216_000,
+            fractional: 123_456_789,
+          }),
+          Type::Color(Color {
+            red: 255,
+            green: 0,
+            blue: 128,
+            alpha: 1,
+          }),
+          Type::Midi(MidiMessage {
+            port: 1,
+            status: 0x90,
+            data1: 60,
+            data2: 127,
+          }),
+          Type::Array(OscArray {
+            content: vec![Type::Array(OscArray { content: vec![] }), Type::Nil],
+          }),
+        ],
+      )),
+      Packet::Bundle(Bundle {
+        timetag: OscTime {
+          seconds: 3_969_216_000,
+          fractional: 1,
+        },
+        content: vec![
+          Packet::from(msg("/a", vec![Type::Inf])).into(),
+          OscPacket::Bundle(Bundle {
+            timetag: OscTime {
+              seconds: 0,
+              fractional: 1,
+            },
+            content: vec![Packet::from(msg("/b", vec![])).into()],
+          }),
+        ],
+      }),
+    ];
+
+    for packet in packets {
+      let bytes = osc::encode(packet.clone()).unwrap();
+      let line = self::packet(&osc::decode(&bytes).unwrap()).to_string();
+      let read = parse(&line).unwrap();
+      assert_eq!(osc::encode(read).unwrap(), bytes, "{}", line);
+    }
+
+    // Only the canonical NaN comes back as it was.
+    let packet = Packet::from(msg(
+      "/nan",
+      vec![Type::Float(-f32::NAN), Type::Double(-f64::NAN)],
+    ));
+    let read = parse(&self::packet(&packet).to_string()).unwrap();
+    let nan = Packet::from(msg(
+      "/nan",
+      vec![Type::Float(f32::NAN), Type::Double(f64::NAN)],
+    ));
+    assert_eq!(osc::encode(read).unwrap(), osc::encode(nan).unwrap());
+  }
+
+  #[test]
+  fn test_short_forms() {
+    let packets = [
+      (
+        r#"{"address":"/a","args":[{"type":"f","value":0.5},{"type":"T"},{"type":"s","value":"x"}]}"#,
+        Packet::from(msg(
+          "/a",
+          vec![Type::Float(0.5), Type::Bool(true), Type::String("x".into())],
+        )),
+      ),
+      (
+        r#"{"address":"/status"}"#,
+        Packet::from(msg("/status", vec![])),
+      ),
+      (
+        r#"{"elements":[{"address":"/a"}]}"#,
+        Packet::Bundle(Bundle {
+          timetag: OscTime {
+            seconds: 0,
+            fractional: 1,
+          },
+          content: vec![Packet::from(msg("/a", vec![])).into()],
+        }),
+      ),
+      (
+        r#"{"timestamp":"2026-10-17T20:00:00Z","port":57110,"packet":{"type":"message","address":"/a","types":",i","args":[{"type":"int","value":1}]}}"#,
+        Packet::from(msg("/a", vec![Type::Int(1)])),
+      ),
+    ];
+
+    for (input, packet) in packets.iter() {
+      assert_eq!(parse(input).as_ref(), Ok(packet), "{}", input);
+    }
+  }
+
+  #[test]
+  fn test_invalid_packets() {
+    assert_eq!(parse(r#"{"address":"/a",}"#).unwrap_err().0, Some(17));
+    for input in [
+      r#"{"address":"a"}"#,
+      r#"{"args":[]}"#,
+      r#"{"address":"/a","args":[{"type":"i","value":2147483648}]}"#,
+      r#"{"address":"/a","args":[{"type":"f","value":"1.5"}]}"#,
+      r#"{"address":"/a","args":[{"type":"c","value":"ab"}]}"#,
+      r#"{"address":"/a","args":[{"type":"b","value":"0g"}]}"#,
+      r#"{"address":"/a","args":[{"type":"b","value":"+f"}]}"#,
+      r#"{"address":"/a","args":[{"type":"q","value":1}]}"#,
+      r#"{"address":"/a","args":[{"type":"int"}]}"#,
+      r#"{"elements":[],"timetag":{"seconds":-1}}"#,
+      r#"[1, 2]"#,
+    ]
+    .iter()
+    {
+      assert_eq!(parse(input).unwrap_err().0, None, "{}", input);
+    }
+  }
+}
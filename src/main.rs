@@ -1,25 +1,353 @@
 #![allow(missing_docs)]
 
 use clap::*;
+use colored::Color;
+use csv::Layout;
 
 mod analyser;
+mod csv;
+mod filter;
+mod hex;
+mod json;
 mod osc;
+mod ports;
 mod prompt;
+mod proxy;
+mod render;
+mod rewrite;
+mod rules;
+mod script;
+mod session;
+mod skew;
 mod table;
 mod task;
+mod time;
 
+use filter::{Filter, Predicate, Source};
+use osc::address::Pattern;
+use osc::multicast::Group;
+use osc::tcp::Framing;
+use osc::SendOptions;
+use ports::{Listen, Proto, Scoped};
 use prompt::prompt;
+use proxy::{Faults, Route};
+use rewrite::Rewrite;
+use std::net::Ipv6Addr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use task::{MonitorOptions, Output, ProxyOptions, ReplayOptions, Syntax, Target};
 
 pub const DEFAULT_IP: &str = "127.0.0.1";
 pub const DEFAULT_PORT: u16 = 57110;
+pub const DEFAULT_BIND: &str = "0.0.0.0";
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
 
 fn main() {
-  let _app = clap_app!(oscd =>
+  let matches = clap_app!(oscd =>
     (version: env!("CARGO_PKG_VERSION"))
     (author: env!("CARGO_PKG_AUTHORS"))
     (about: env!("CARGO_PKG_DESCRIPTION"))
+    (@subcommand monitor =>
+      (about: "monitor OSC messages sent to a port")
+      (@arg port: -p --port +takes_value +multiple_occurrences value_parser(Listen::parse) "port to listen on, can be repeated to listen on several ports at once, eg. 9000/tcp to pick its protocol [default: 57110]")
+      (@arg bind: -b --bind +takes_value "local address to listen on, :: listens on IPv6 and IPv4 [default: 0.0.0.0]")
+      (@arg proto: --proto +takes_value value_parser(["udp", "tcp"]) "transport protocol, udp or tcp [default: udp]")
+      (@arg framing: --framing +takes_value value_parser(Framing::from_str) "how packets are delimited over tcp, slip (OSC 1.1), length (OSC 1.0 int32 size prefix) or auto to detect it for each connection [default: auto], implies --proto tcp")
+      (@arg filter: -f --filter +takes_value +multiple_occurrences value_parser(ports::scoped(Pattern::parse)) "only show messages matching this address pattern, eg. '/light/{1,2}/*', or only on a port with '8000=/light/*'")
+      (@arg exclude: -x --exclude +takes_value +multiple_occurrences value_parser(ports::scoped(Pattern::parse)) "hide messages matching this address pattern, on every port or on one with PORT=PATTERN")
+      (@arg from: --from +takes_value +multiple_occurrences value_parser(ports::scoped(Source::parse)) "only show packets sent from this IP, socket address or CIDR range, eg. 10.0.0.0/24, on every port or on one with PORT=SOURCE")
+      (@arg color: -c --color +takes_value +multiple_occurrences value_parser(ports::scoped(ports::parse_color)) "colour of the tag showing which port packets arrived on, eg. 8000=yellow, or of every tag")
+      (@arg join: -j --join +takes_value +multiple_occurrences value_parser(Group::parse) "join this multicast group, optionally on the interface with the given IPv4 address or IPv6 index, eg. 239.1.2.3@192.168.1.10 or ff12::1234@2")
+      (@arg record: -r --record +takes_value value_parser(value_parser!(PathBuf)) "record every received datagram to this session file")
+      (@arg where: -w --where +takes_value +multiple_occurrences value_parser(ports::scoped(Predicate::parse)) "only show messages whose arguments satisfy this predicate, eg. 'arg[1] > 0.5', on every port or on one with PORT=PREDICATE")
+      (@arg csv: --csv +takes_value value_parser(value_parser!(PathBuf)) "export the messages shown to CSV files in this directory, one per address with a column per argument, at most 128 of them open at once")
+      (@arg csv_long: --("csv-long") +takes_value value_parser(value_parser!(PathBuf)) conflicts_with[csv] "export the messages shown to this CSV file in long format, a row per argument")
+      (@arg output: -o --output +takes_value value_parser(["table", "json"]) "how received packets are shown, table or json (one JSON object per line, eg. for jq) [default: table]")
+    )
+    (@subcommand proxy =>
+      (about: "forward OSC packets to other hosts and their replies back, showing what passes through")
+      (@arg port: -p --port +takes_value value_parser(value_parser!(u16)) "port to listen on [default: 57110]")
+      (@arg bind: -b --bind +takes_value "local address to listen on [default: 0.0.0.0]")
+      (@arg to: -t --to +takes_value +multiple_occurrences required_unless_present("route") value_parser(proxy::parse_destination) "forward packets to this HOST:PORT, can be repeated, eg. 10.0.0.5:57110")
+      (@arg route: --route +takes_value +multiple_occurrences value_parser(Route::parse) "forward packets holding a message matching PATTERN to HOST:PORT instead, eg. '/light/*=10.0.0.6:8000'")
+      (@arg rules: --rules +takes_value value_parser(value_parser!(PathBuf)) "transform forwarded messages with the rules of this file, eg. '/fader/{n} -> /mixer/ch/{n}/gain $0(0..127=0..1)'")
+      (@arg drop: --drop +takes_value value_parser(proxy::parse_probability) "drop this share of the packets, eg. 5%")
+      (@arg duplicate: --duplicate +takes_value value_parser(proxy::parse_probability) "send this share of the packets twice, eg. 1%")
+      (@arg delay: --delay +takes_value value_parser(time::parse_duration) "hold every packet back this long, eg. 50ms")
+    )
+    (@subcommand query =>
+      (about: "send a message and wait for the reply, eg. /status and its /status.reply")
+      (@arg host: -H --host +takes_value "host to send to, an IPv4 or IPv6 address (eg. fe80::1%eth0) or a hostname [default: 127.0.0.1]")
+      (@arg port: -p --port +takes_value value_parser(value_parser!(u16)) "port to send to [default: 57110]")
+      (@arg proto: --proto +takes_value value_parser(["udp", "tcp"]) "transport protocol, udp or tcp [default: udp]")
+      (@arg framing: --framing +takes_value value_parser(parse_sending_framing) "how packets are delimited over tcp, slip (OSC 1.1) or length (OSC 1.0 int32 size prefix) [default: slip], implies --proto tcp")
+      (@arg broadcast: --broadcast "allow sending to a broadcast address, eg. 192.168.1.255 (always allowed for 255.255.255.255)")
+      (@arg ttl: --ttl +takes_value value_parser(value_parser!(u32)) "how many routers IPv4 multicast packets may cross, the hop limit of IPv6 ones cannot be set [default: 1]")
+      (@arg no_loopback: --("no-loopback") "do not deliver multicast packets to listeners on this host")
+      (@arg expect: -e --expect +takes_value value_parser(Pattern::parse) "only accept a reply matching this address pattern, eg. /status.reply [default: any reply]")
+      (@arg timeout: -t --timeout +takes_value value_parser(time::parse_duration) "how long to wait for the reply, eg. 500ms [default: 1s]")
+      (@arg MESSAGE: +required "message to send, eg. /status")
+    )
+    (@subcommand replay =>
+      (about: "re-send the datagrams of a session recorded with `monitor --record`, with their original timing")
+      (@arg SESSION: +required value_parser(value_parser!(PathBuf)) "session file to replay")
+      (@arg host: -H --host +takes_value "host to send to, an IPv4 or IPv6 address (eg. fe80::1%eth0) or a hostname [default: 127.0.0.1]")
+      (@arg port: -p --port +takes_value value_parser(value_parser!(u16)) "port to send to [default: 57110]")
+      (@arg speed: -s --speed +takes_value value_parser(parse_speed) "playback speed multiplier, eg. 2 plays twice as fast [default: 1]")
+      (@arg repeat: -l --loop "start over once the end of the session is reached")
+      (@arg start: --start +takes_value value_parser(time::parse_duration) "skip what was received before this offset into the session, eg. 1.5s")
+      (@arg end: --end +takes_value value_parser(time::parse_duration) "stop at this offset into the session, eg. 2m")
+      (@arg filter: -f --filter +takes_value +multiple_occurrences value_parser(Pattern::parse) "only replay messages matching this address pattern")
+      (@arg exclude: -x --exclude +takes_value +multiple_occurrences value_parser(Pattern::parse) "do not replay messages matching this address pattern")
+      (@arg rewrite: --rewrite +takes_value +multiple_occurrences value_parser(Rewrite::parse) "replace the leading address parts matching FROM with TO, eg. '/synth/*=/visual'")
+    )
+    (@subcommand run =>
+      (about: "send the messages of a script file, one per line, with `wait 500ms` lines to pause")
+      (@arg SCRIPT: +required value_parser(value_parser!(PathBuf)) "script file to run, eg. show.osc")
+      (@arg host: -H --host +takes_value "host to send to, an IPv4 or IPv6 address (eg. fe80::1%eth0) or a hostname [default: 127.0.0.1]")
+      (@arg port: -p --port +takes_value value_parser(value_parser!(u16)) "port to send to [default: 57110]")
+      (@arg proto: --proto +takes_value value_parser(["udp", "tcp"]) "transport protocol, udp or tcp [default: udp]")
+      (@arg framing: --framing +takes_value value_parser(parse_sending_framing) "how packets are delimited over tcp, slip (OSC 1.1) or length (OSC 1.0 int32 size prefix) [default: slip], implies --proto tcp")
+      (@arg broadcast: --broadcast "allow sending to a broadcast address, eg. 192.168.1.255 (always allowed for 255.255.255.255)")
+      (@arg ttl: --ttl +takes_value value_parser(value_parser!(u32)) "how many routers IPv4 multicast packets may cross, the hop limit of IPv6 ones cannot be set [default: 1]")
+      (@arg no_loopback: --("no-loopback") "do not deliver multicast packets to listeners on this host")
+      (@arg json: --json "every line of the script is a JSON object as printed by monitor --output json, eg. a .jsonl file")
+    )
+    (@subcommand send =>
+      (about: "send OSC messages to a host, interactively, once when MESSAGE is given, or line by line from stdin")
+      (@arg host: -H --host +takes_value "host to send to, an IPv4 or IPv6 address (eg. fe80::1%eth0) or a hostname [default: 127.0.0.1]")
+      (@arg port: -p --port +takes_value value_parser(value_parser!(u16)) "port to send to [default: 57110]")
+      (@arg proto: --proto +takes_value value_parser(["udp", "tcp"]) "transport protocol, udp or tcp [default: udp]")
+      (@arg framing: --framing +takes_value value_parser(parse_sending_framing) "how packets are delimited over tcp, slip (OSC 1.1) or length (OSC 1.0 int32 size prefix) [default: slip], implies --proto tcp")
+      (@arg broadcast: --broadcast "allow sending to a broadcast address, eg. 192.168.1.255 (always allowed for 255.255.255.255)")
+      (@arg ttl: --ttl +takes_value value_parser(value_parser!(u32)) "how many routers IPv4 multicast packets may cross, the hop limit of IPv6 ones cannot be set [default: 1]")
+      (@arg no_loopback: --("no-loopback") "do not deliver multicast packets to listeners on this host")
+      (@arg schedule: -S --schedule "hold bundles and send them at the time of their timetag, for receivers that ignore timetags")
+      (@arg json: --json "MESSAGE, or the lines read from stdin, are JSON objects as printed by monitor --output json, eg. '{\"address\":\"/a\",\"args\":[{\"type\":\"f\",\"value\":0.5}]}'")
+      (@arg keep_going: -k --("keep-going") "when reading stdin, report the lines that cannot be sent and carry on instead of stopping")
+      (@arg MESSAGE: "message to send once, eg. '/s_new \"default\" -1 0 0', or - to send every line read from stdin (the default when stdin is not a terminal)")
+    )
   )
   .get_matches();
 
-  prompt().unwrap();
+  let result = match matches.subcommand() {
+    Some(("monitor", sub)) => {
+      let bind = host(sub, "bind", DEFAULT_BIND);
+      let mut listens = values::<Listen>(sub, "port");
+      if listens.is_empty() {
+        listens.push(Listen {
+          port: DEFAULT_PORT,
+          proto: None,
+        });
+      }
+      let picks_tcp = listens
+        .iter()
+        .any(|listen| listen.proto == Some(Proto::Tcp));
+      let default_transport = match (sub.value_of("proto"), picks_tcp) {
+        // The framing is meant for the ports picked as TCP then.
+        (Some("udp"), true) => osc::Transport::Udp,
+        _ => transport(sub, Framing::Auto),
+      };
+      let framing = sub
+        .get_one::<Framing>("framing")
+        .copied()
+        .unwrap_or(Framing::Auto);
+      let ports = listens
+        .iter()
+        .map(|listen| task::Port {
+          port: listen.port,
+          transport: match listen.proto {
+            Some(Proto::Udp) => osc::Transport::Udp,
+            Some(Proto::Tcp) => osc::Transport::Tcp(framing),
+            None => default_transport,
+          },
+          filter: Filter::new(
+            scoped::<Pattern>(sub, "filter", listen.port),
+            scoped::<Pattern>(sub, "exclude", listen.port),
+            scoped::<Source>(sub, "from", listen.port),
+            scoped::<Predicate>(sub, "where", listen.port),
+          ),
+          color: scoped::<Color>(sub, "color", listen.port).pop(),
+        })
+        .collect();
+      task::monitor(MonitorOptions {
+        bind,
+        ports,
+        groups: values::<Group>(sub, "join"),
+        record: sub.get_one::<PathBuf>("record").cloned(),
+        csv: match (
+          sub.get_one::<PathBuf>("csv"),
+          sub.get_one::<PathBuf>("csv_long"),
+        ) {
+          (Some(dir), _) => Some(Layout::PerAddress(dir.clone())),
+          (None, Some(path)) => Some(Layout::Long(path.clone())),
+          (None, None) => None,
+        },
+        output: match sub.value_of("output") {
+          Some("json") => Output::Json,
+          _ => Output::Table,
+        },
+      })
+    }
+    Some(("proxy", sub)) => task::proxy(ProxyOptions {
+      bind: host(sub, "bind", DEFAULT_BIND),
+      port: sub.get_one::<u16>("port").copied().unwrap_or(DEFAULT_PORT),
+      to: values::<String>(sub, "to"),
+      routes: values::<Route>(sub, "route"),
+      rules: sub.get_one::<PathBuf>("rules").cloned(),
+      faults: Faults {
+        drop: sub.get_one::<f64>("drop").copied().unwrap_or(0.0),
+        duplicate: sub.get_one::<f64>("duplicate").copied().unwrap_or(0.0),
+        delay: sub
+          .get_one::<Duration>("delay")
+          .copied()
+          .unwrap_or_default(),
+      },
+    }),
+    Some(("query", sub)) => task::query(
+      target(sub),
+      sub.value_of("MESSAGE").unwrap_or_default(),
+      sub.get_one::<Pattern>("expect").cloned(),
+      sub
+        .get_one::<Duration>("timeout")
+        .copied()
+        .unwrap_or(DEFAULT_TIMEOUT),
+    ),
+    Some(("replay", sub)) => task::replay(ReplayOptions {
+      session: sub
+        .get_one::<PathBuf>("SESSION")
+        .cloned()
+        .unwrap_or_default(),
+      host: host(sub, "host", DEFAULT_IP),
+      port: sub.get_one::<u16>("port").copied().unwrap_or(DEFAULT_PORT),
+      speed: sub.get_one::<f64>("speed").copied().unwrap_or(1.0),
+      repeat: sub.is_present("repeat"),
+      start: sub.get_one::<Duration>("start").copied(),
+      end: sub.get_one::<Duration>("end").copied(),
+      filter: Filter::new(
+        values::<Pattern>(sub, "filter"),
+        values::<Pattern>(sub, "exclude"),
+        vec![],
+        vec![],
+      ),
+      rewrites: values::<Rewrite>(sub, "rewrite"),
+    }),
+    Some(("run", sub)) => task::run(
+      sub
+        .get_one::<PathBuf>("SCRIPT")
+        .cloned()
+        .unwrap_or_default(),
+      target(sub),
+      syntax(sub),
+    ),
+    Some(("send", sub)) => {
+      let schedule = sub.is_present("schedule");
+      let keep_going = sub.is_present("keep_going");
+      let syntax = syntax(sub);
+      match sub.value_of("MESSAGE") {
+        Some("-") => task::send_stdin(target(sub), schedule, keep_going, syntax),
+        Some(input) => task::send_once(target(sub), input, schedule, syntax),
+        None if !termion::is_tty(&std::io::stdin()) => {
+          task::send_stdin(target(sub), schedule, keep_going, syntax)
+        }
+        None => task::send(target(sub), schedule),
+      }
+    }
+    _ => prompt().unwrap(),
+  };
+
+  if let Err(failure) = result {
+    std::process::exit(failure.exit_code());
+  }
+}
+
+fn syntax(matches: &ArgMatches) -> Syntax {
+  match matches.is_present("json") {
+    true => Syntax::Json,
+    false => Syntax::Literal,
+  }
+}
+
+fn values<T: Clone + Send + Sync + 'static>(matches: &ArgMatches, id: &str) -> Vec<T> {
+  matches
+    .get_many::<T>(id)
+    .map(|values| values.cloned().collect())
+    .unwrap_or_default()
+}
+
+// The values of a `Scoped` option that apply to `port`, in the order they were given.
+fn scoped<T: Clone + Send + Sync + 'static>(matches: &ArgMatches, id: &str, port: u16) -> Vec<T> {
+  values::<Scoped<T>>(matches, id)
+    .into_iter()
+    .filter(|scoped| scoped.applies_to(port))
+    .map(|scoped| scoped.value)
+    .collect()
+}
+
+fn target(matches: &ArgMatches) -> Target {
+  let host = host(matches, "host", DEFAULT_IP);
+  let ttl = matches.get_one::<u32>("ttl").copied();
+  // The standard library has no way to set the hop limit of IPv6 multicast packets.
+  let scopeless = host.split('%').next().unwrap_or(&host);
+  if ttl.is_some() && scopeless.parse::<Ipv6Addr>().is_ok() {
+    Error::raw(
+      ErrorKind::ArgumentConflict,
+      "The argument '--ttl <ttl>' cannot be used with an IPv6 host, it only applies to IPv4 multicast\n",
+    )
+    .exit()
+  }
+  Target {
+    host,
+    port: matches
+      .get_one::<u16>("port")
+      .copied()
+      .unwrap_or(DEFAULT_PORT),
+    transport: transport(matches, Framing::Slip),
+    options: SendOptions {
+      broadcast: matches.is_present("broadcast"),
+      ttl,
+      loopback: !matches.is_present("no_loopback"),
+    },
+  }
+}
+
+// IPv6 addresses may be written between brackets, as in socket addresses.
+fn host(matches: &ArgMatches, id: &str, default: &str) -> String {
+  let host = matches.value_of(id).unwrap_or(default);
+  host
+    .strip_prefix('[')
+    .and_then(|host| host.strip_suffix(']'))
+    .unwrap_or(host)
+    .to_string()
+}
+
+// Picking a framing is enough to switch to TCP, unless UDP was picked explicitly.
+fn transport(matches: &ArgMatches, default_framing: Framing) -> osc::Transport {
+  let framing = matches.get_one::<Framing>("framing").copied();
+  match (matches.value_of("proto"), framing) {
+    (Some("udp"), Some(_)) => Error::raw(
+      ErrorKind::ArgumentConflict,
+      "The argument '--framing <framing>' cannot be used with '--proto udp', framing only applies to tcp\n",
+    )
+    .exit(),
+    (Some("tcp"), None) => osc::Transport::Tcp(default_framing),
+    (_, Some(framing)) => osc::Transport::Tcp(framing),
+    _ => osc::Transport::Udp,
+  }
+}
+
+// Only received packets can have their framing detected.
+fn parse_sending_framing(framing: &str) -> Result<Framing, String> {
+  match Framing::from_str(framing)? {
+    Framing::Auto => Err("`auto` only applies to monitor, use slip or length".to_string()),
+    framing => Ok(framing),
+  }
+}
+
+fn parse_speed(speed: &str) -> Result<f64, String> {
+  match speed.parse::<f64>() {
+    Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+    _ => Err(format!("`{}` should be a positive number, eg. 0.5", speed)),
+  }
 }
@@ -0,0 +1,228 @@
+//! OSC address pattern matching.
+//!
+//! Supports the OSC 1.0 wildcards `?`, `*`, `[a-z]`, `[!a-z]` and `{foo,bar}` within a single
+//! address part, as well as the OSC 1.1 path-traversing wildcard `//`, which matches any number
+//! of address parts, eg. `//volume` matches both `/volume` and `/mixer/1/volume`.
+
+use std::fmt;
+
+/// A compiled OSC address pattern.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+  source: String,
+  // `None` stands for the `//` path-traversing wildcard.
+  parts: Vec<Option<Vec<Token>>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Char(char),
+  AnyChar,
+  AnySequence,
+  Set {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+  },
+  Alternatives(Vec<Vec<char>>),
+}
+
+impl Pattern {
+  /// Compiles the given address pattern.
+  ///
+  /// Returns an error describing the problem if the pattern does not start with `/` or contains
+  /// an unclosed `[` or `{`.
+  pub fn parse(pattern: &str) -> Result<Pattern, String> {
+    let rest = match pattern.strip_prefix('/') {
+      Some(rest) => rest,
+      None => return Err(format!("`{}` should start with / eg. /s_new", pattern)),
+    };
+    let mut parts = vec![];
+    let segments = rest.split('/').collect::<Vec<&str>>();
+    for (i, segment) in segments.iter().enumerate() {
+      if segment.is_empty() && i + 1 < segments.len() {
+        // Consecutive `//` collapse into a single path-traversing wildcard.
+        if parts.last() != Some(&None) {
+          parts.push(None);
+        }
+      } else {
+        parts.push(Some(
+          tokens(segment).map_err(|e| format!("`{}` {}", pattern, e))?,
+        ));
+      }
+    }
+    Ok(Pattern {
+      source: pattern.to_string(),
+      parts,
+    })
+  }
+
+  /// Returns whether the given OSC address matches this pattern.
+  pub fn matches(&self, addr: &str) -> bool {
+    match addr.strip_prefix('/') {
+      Some(rest) => {
+        let parts = rest
+          .split('/')
+          .map(|part| part.chars().collect())
+          .collect::<Vec<Vec<char>>>();
+        match_parts(&self.parts, &parts)
+      }
+      None => false,
+    }
+  }
+}
+
+impl fmt::Display for Pattern {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.source)
+  }
+}
+
+fn tokens(segment: &str) -> Result<Vec<Token>, String> {
+  let mut tokens = vec![];
+  let mut chars = segment.chars().peekable();
+  while let Some(c) = chars.next() {
+    let token = match c {
+      '?' => Token::AnyChar,
+      '*' => Token::AnySequence,
+      '[' => {
+        let negated = chars.peek() == Some(&'!');
+        if negated {
+          chars.next();
+        }
+        let mut set = vec![];
+        loop {
+          match chars.next() {
+            Some(']') => break,
+            Some(start) => set.push(start),
+            None => return Err("has an unclosed `[`".to_string()),
+          }
+        }
+        let mut ranges = vec![];
+        let mut i = 0;
+        while i < set.len() {
+          // A `-` at either end of the set is taken literally.
+          if i + 2 < set.len() && set[i + 1] == '-' {
+            ranges.push((set[i], set[i + 2]));
+            i += 3;
+          } else {
+            ranges.push((set[i], set[i]));
+            i += 1;
+          }
+        }
+        Token::Set { negated, ranges }
+      }
+      '{' => {
+        let mut alternatives = vec![vec![]];
+        loop {
+          match chars.next() {
+            Some('}') => break,
+            Some(',') => alternatives.push(vec![]),
+            Some(c) => alternatives.last_mut().unwrap().push(c),
+            None => return Err("has an unclosed `{`".to_string()),
+          }
+        }
+        Token::Alternatives(alternatives)
+      }
+      c => Token::Char(c),
+    };
+    tokens.push(token);
+  }
+  Ok(tokens)
+}
+
+fn match_parts(pattern: &[Option<Vec<Token>>], addr: &[Vec<char>]) -> bool {
+  match pattern.split_first() {
+    None => addr.is_empty(),
+    Some((None, rest)) => (0..=addr.len()).any(|skip| match_parts(rest, &addr[skip..])),
+    Some((Some(tokens), rest)) => match addr.split_first() {
+      Some((part, addr)) => match_tokens(tokens, part) && match_parts(rest, addr),
+      None => false,
+    },
+  }
+}
+
+fn match_tokens(tokens: &[Token], part: &[char]) -> bool {
+  let (token, rest) = match tokens.split_first() {
+    Some(split) => split,
+    None => return part.is_empty(),
+  };
+  match token {
+    Token::Char(c) => part.first() == Some(c) && match_tokens(rest, &part[1..]),
+    Token::AnyChar => !part.is_empty() && match_tokens(rest, &part[1..]),
+    Token::AnySequence => (0..=part.len()).any(|skip| match_tokens(rest, &part[skip..])),
+    Token::Set { negated, ranges } => match part.first() {
+      Some(c) => {
+        let in_set = ranges.iter().any(|(start, end)| start <= c && c <= end);
+        in_set != *negated && match_tokens(rest, &part[1..])
+      }
+      None => false,
+    },
+    Token::Alternatives(alternatives) => alternatives
+      .iter()
+      .any(|alt| part.starts_with(alt) && match_tokens(rest, &part[alt.len()..])),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_matching_addresses() {
+    let matching = [
+      ("/cue/selected/level", "/cue/selected/level"),
+      ("/cue/*/level", "/cue/selected/level"),
+      ("/cue/sel*", "/cue/selected"),
+      ("/cue/*ed", "/cue/selected"),
+      ("/press/bank/?/1", "/press/bank/3/1"),
+      ("/ch/[0-9]", "/ch/7"),
+      ("/ch/[!0-9]", "/ch/x"),
+      ("/ch/[abc-]", "/ch/-"),
+      ("/{synth,drum}/gate", "/drum/gate"),
+      ("/{synth,drum}*/gate", "/synth2/gate"),
+      ("//volume", "/volume"),
+      ("//volume", "/mixer/1/volume"),
+      ("/mixer//gain", "/mixer/ch/1/gain"),
+      ("/mixer///gain", "/mixer/gain"),
+    ];
+
+    for (pattern, addr) in matching.iter() {
+      assert!(
+        Pattern::parse(pattern).unwrap().matches(addr),
+        "{} should match {}",
+        pattern,
+        addr
+      );
+    }
+  }
+
+  #[test]
+  fn test_non_matching_addresses() {
+    let non_matching = [
+      ("/cue/selected", "/cue/selected/level"),
+      ("/cue/*", "/cue/selected/level"),
+      ("/press/bank/?/1", "/press/bank/10/1"),
+      ("/ch/[0-9]", "/ch/x"),
+      ("/ch/[!0-9]", "/ch/7"),
+      ("/{synth,drum}/gate", "/bass/gate"),
+      ("//volume", "/mixer/1/volume/2"),
+      ("/a", "a"),
+    ];
+
+    for (pattern, addr) in non_matching.iter() {
+      assert!(
+        !Pattern::parse(pattern).unwrap().matches(addr),
+        "{} should not match {}",
+        pattern,
+        addr
+      );
+    }
+  }
+
+  #[test]
+  fn test_invalid_patterns() {
+    for pattern in ["cue/*", "/ch/[0-9", "/{synth,drum/gate"].iter() {
+      assert!(Pattern::parse(pattern).is_err(), "{}", pattern);
+    }
+  }
+}
@@ -0,0 +1,324 @@
+//! Tools for looking into datagrams that could not be decoded into an OSC `Packet`.
+//!
+//! [**diagnose(bytes)**](./fn.diagnose.html) walks the raw bytes following the OSC 1.0 encoding
+//! rules and reports where they stop making sense, [**hex_dump(bytes)**](./fn.hex_dump.html)
+//! formats them for display.
+
+use std::fmt;
+
+/// Describes where, and why, the encoding of a datagram broke.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnosis {
+  /// Byte offset from the start of the datagram at which the problem was found.
+  pub offset: usize,
+  /// A human readable explanation of the problem.
+  pub reason: String,
+}
+
+impl fmt::Display for Diagnosis {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "at byte {}: {}", self.offset, self.reason)
+  }
+}
+
+/// Formats `bytes` as a classic hex dump, 16 bytes per line, with the offset on the left and the
+/// printable ASCII characters on the right.
+pub fn hex_dump(bytes: &[u8]) -> String {
+  bytes
+    .chunks(16)
+    .enumerate()
+    .map(|(i, chunk)| {
+      let hex = chunk
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(" ");
+      let ascii = chunk
+        .iter()
+        .map(|&b| {
+          if b.is_ascii_graphic() || b == b' ' {
+            b as char
+          } else {
+            '.'
+          }
+        })
+        .collect::<String>();
+      format!("{:08x}  {:<47}  |{}|", i * 16, hex, ascii)
+    })
+    .collect::<Vec<String>>()
+    .join("\n")
+}
+
+/// Walks `bytes` as an OSC packet and returns the first problem found, or `None` if the
+/// structure of the packet looks valid.
+///
+/// This is a best-effort explanation meant for display, it does not replace the decoder.
+pub fn diagnose(bytes: &[u8]) -> Option<Diagnosis> {
+  if bytes.is_empty() {
+    return Some(fail(0, "the datagram is empty"));
+  }
+  packet(bytes, 0).err()
+}
+
+fn fail<S: Into<String>>(offset: usize, reason: S) -> Diagnosis {
+  Diagnosis {
+    offset,
+    reason: reason.into(),
+  }
+}
+
+// `base` is the offset of `bytes` within the whole datagram, so that nested bundle elements are
+// reported relative to the start of what was received.
+fn packet(bytes: &[u8], base: usize) -> Result<(), Diagnosis> {
+  if bytes.len() % 4 != 0 {
+    return Err(fail(
+      base,
+      format!(
+        "packet size of {} bytes is not a multiple of 4",
+        bytes.len()
+      ),
+    ));
+  }
+  match bytes.first() {
+    Some(b'/') => message(bytes, base),
+    Some(b'#') => bundle(bytes, base),
+    Some(b) => Err(fail(
+      base,
+      format!(
+        "a packet must start with `/` (message) or `#bundle`, found 0x{:02x}",
+        b
+      ),
+    )),
+    None => Err(fail(base, "empty bundle element")),
+  }
+}
+
+fn bundle(bytes: &[u8], base: usize) -> Result<(), Diagnosis> {
+  let (tag, mut pos) = string(bytes, 0, base, "bundle tag")?;
+  if tag != "#bundle" {
+    return Err(fail(
+      base,
+      format!("expected `#bundle` tag, found {:?}", tag),
+    ));
+  }
+  if bytes.len() < pos + 8 {
+    return Err(fail(base + pos, "bundle timetag is truncated"));
+  }
+  pos += 8;
+  while pos < bytes.len() {
+    if bytes.len() < pos + 4 {
+      return Err(fail(base + pos, "bundle element size is truncated"));
+    }
+    let size = i32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]);
+    pos += 4;
+    let remaining = bytes.len() - pos;
+    if size < 0 || size as usize > remaining {
+      return Err(fail(
+        base + pos - 4,
+        format!(
+          "bundle element claims {} bytes but only {} remain",
+          size, remaining
+        ),
+      ));
+    }
+    let size = size as usize;
+    packet(&bytes[pos..pos + size], base + pos)?;
+    pos += size;
+  }
+  Ok(())
+}
+
+fn message(bytes: &[u8], base: usize) -> Result<(), Diagnosis> {
+  let (_, mut pos) = string(bytes, 0, base, "address")?;
+  if pos >= bytes.len() {
+    // OSC 1.0 asks receivers to tolerate older implementations that leave it out, but the decoder
+    // does not, and only packets it already refused get diagnosed.
+    return Err(fail(
+      base + pos,
+      "the type tag string is missing, as sent by some pre OSC 1.0 implementations",
+    ));
+  }
+  if bytes[pos] != b',' {
+    return Err(fail(
+      base + pos,
+      format!(
+        "the type tag string must start with `,`, found 0x{:02x}",
+        bytes[pos]
+      ),
+    ));
+  }
+  let tags_offset = pos;
+  let (tags, args_pos) = string(bytes, pos, base, "type tag string")?;
+  pos = args_pos;
+
+  let mut depth = 0;
+  for (i, tag) in tags.chars().enumerate().skip(1) {
+    let tag_offset = base + tags_offset + i;
+    let size = match tag {
+      'i' | 'f' | 'c' | 'r' | 'm' => 4,
+      'h' | 'd' | 't' => 8,
+      'T' | 'F' | 'N' | 'I' => 0,
+      '[' => {
+        depth += 1;
+        0
+      }
+      ']' => {
+        if depth == 0 {
+          return Err(fail(
+            tag_offset,
+            "array closed with `]` without being opened",
+          ));
+        }
+        depth -= 1;
+        0
+      }
+      's' | 'S' => {
+        let (_, next) = string(bytes, pos, base, &format!("string argument `{}`", tag))?;
+        pos = next;
+        continue;
+      }
+      'b' => {
+        if bytes.len() < pos + 4 {
+          return Err(truncated(tag, 4, bytes.len() - pos, base + pos));
+        }
+        let size = i32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]);
+        if size < 0 {
+          return Err(fail(base + pos, format!("blob size {} is negative", size)));
+        }
+        4 + pad(size as usize)
+      }
+      _ => return Err(fail(tag_offset, format!("unknown type tag {:?}", tag))),
+    };
+    if bytes.len() < pos + size {
+      return Err(truncated(tag, size, bytes.len() - pos, base + pos));
+    }
+    pos += size;
+  }
+  if depth != 0 {
+    return Err(fail(
+      base + tags_offset,
+      "array opened with `[` is never closed",
+    ));
+  }
+  if pos != bytes.len() {
+    return Err(fail(
+      base + pos,
+      format!(
+        "{} unexpected bytes after the last argument",
+        bytes.len() - pos
+      ),
+    ));
+  }
+  Ok(())
+}
+
+fn truncated(tag: char, needed: usize, remaining: usize, offset: usize) -> Diagnosis {
+  fail(
+    offset,
+    format!(
+      "argument `{}` is truncated, it needs {} bytes but only {} remain",
+      tag, needed, remaining
+    ),
+  )
+}
+
+// Reads a null terminated, 4-byte padded string starting at `pos` and returns it along with the
+// position right after its padding.
+fn string(bytes: &[u8], pos: usize, base: usize, what: &str) -> Result<(String, usize), Diagnosis> {
+  let end = match bytes[pos..].iter().position(|&b| b == 0) {
+    Some(len) => pos + len,
+    None => return Err(fail(base + pos, format!("{} is not null terminated", what))),
+  };
+  let value = match std::str::from_utf8(&bytes[pos..end]) {
+    Ok(value) => value.to_string(),
+    Err(e) => {
+      return Err(fail(
+        base + pos + e.valid_up_to(),
+        format!("{} is not valid UTF-8", what),
+      ))
+    }
+  };
+  let next = pos + pad(end - pos + 1);
+  if next > bytes.len() {
+    return Err(fail(
+      base + end,
+      format!(
+        "bad {} padding, it should end at byte {}",
+        what,
+        base + next
+      ),
+    ));
+  }
+  if let Some(i) = bytes[end..next].iter().position(|&b| b != 0) {
+    return Err(fail(
+      base + end + i,
+      format!("bad {} padding, padding bytes must be zero", what),
+    ));
+  }
+  Ok((value, next))
+}
+
+fn pad(len: usize) -> usize {
+  (len + 3) & !3
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_valid_packets() {
+    let valid = [
+      // /a ,is 1 "hi"
+      b"/a\0\0,is\0\0\0\0\x01hi\0\0".to_vec(),
+      // #bundle, immediately, containing /b ,
+      b"#bundle\0\0\0\0\0\0\0\0\x01\0\0\0\x08/b\0\0,\0\0\0".to_vec(),
+    ];
+
+    for bytes in valid.iter() {
+      assert_eq!(diagnose(bytes), None);
+    }
+  }
+
+  #[test]
+  fn test_invalid_packets() {
+    let invalid = [
+      (b"".to_vec(), 0, "the datagram is empty"),
+      (b"/ab\0,i\0\0\0\0".to_vec(), 0, "not a multiple of 4"),
+      (b"/a\0x,i\0\0\0\0\0\x01".to_vec(), 3, "bad address padding"),
+      (b"/a\0\0,x\0\0".to_vec(), 5, "unknown type tag 'x'"),
+      (
+        b"/a\0\0,ii\0\0\0\0\x01".to_vec(),
+        12,
+        "argument `i` is truncated",
+      ),
+      (b"xa\0\0,i\0\0\0\0\0\x01".to_vec(), 0, "must start with `/`"),
+      (b"/a\0\0".to_vec(), 4, "the type tag string is missing"),
+      (
+        b"#bundle\0\0\0\0\0\0\0\0\x01\0\0\0\x10/b\0\0,\0\0\0".to_vec(),
+        16,
+        "claims 16 bytes but only 8 remain",
+      ),
+      (
+        b"#bundle\0\0\0\0\0\0\0\0\x01\0\0\0\x08/b\0\0,y\0\0".to_vec(),
+        25,
+        "unknown type tag 'y'",
+      ),
+    ];
+
+    for (bytes, offset, reason) in invalid.iter() {
+      let diagnosis = diagnose(bytes).expect("expected a diagnosis");
+      assert_eq!(diagnosis.offset, *offset, "{}", diagnosis);
+      assert!(diagnosis.reason.contains(reason), "{}", diagnosis);
+    }
+  }
+
+  #[test]
+  fn test_hex_dump() {
+    assert_eq!(
+      hex_dump(b"/a\0\0,i\0\0\0\0\0\x01/longer"),
+      "00000000  2f 61 00 00 2c 69 00 00 00 00 00 01 2f 6c 6f 6e  |/a..,i....../lon|\n\
+       00000010  67 65 72                                         |ger|"
+    );
+  }
+}
@@ -1,5 +1,6 @@
 //! Tools for working with OSC. [**sender()**](./fn.sender.html) creates an OSC sender,
-//! [**receiver(port)**](./fn.receiver.html) creates an OSC receiver.
+//! [**receiver(port)**](./fn.receiver.html) creates an OSC receiver,
+//! [**Scheduler**](./struct.Scheduler.html) sends packets through a sender at a given time.
 
 pub use rosc;
 use rosc::OscPacket;
@@ -8,19 +9,27 @@ use rosc::OscPacket;
 //
 // Remove `Osc` prefix as items are already namespaced via a module, e.g. `OscMessage` becomes
 // `nannou_osc::Message`.
+pub use self::multicast::SendOptions;
 pub use self::recv::Receiver;
 #[doc(inline)]
 pub use self::rosc::{
   decoder, encoder, OscBundle as Bundle, OscColor as Color, OscError as Error,
   OscMessage as Message, OscMidiMessage as MidiMessage, OscType as Type,
 };
+pub use self::schedule::Scheduler;
 pub use self::send::Sender;
 
 use std;
-use std::net::{Ipv4Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
 
+pub mod address;
+pub mod inspect;
+pub mod multicast;
 pub mod recv;
+pub mod schedule;
 pub mod send;
+pub mod tcp;
 
 /// Indicates that a `Sender` is not currently connected to a target address, and that the target
 /// address will have to be supplied manually when sending packets.
@@ -31,6 +40,109 @@ pub struct Connected {
   addr: SocketAddr,
 }
 
+/// How packets travel between oscd and the other end.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transport {
+  Udp,
+  Tcp(tcp::Framing),
+}
+
+/// A sender connected to a single target, over either transport.
+pub enum Connection {
+  Udp(Sender<Connected>),
+  Tcp(tcp::Sender),
+}
+
+impl Connection {
+  /// Connects to `addr`, binding UDP senders to a free local port of the same IP family and
+  /// applying `options` to them.
+  ///
+  /// When `addr` resolves to several addresses, eg. a hostname with both IPv6 and IPv4
+  /// addresses, they are tried in turn until one can be connected to.
+  pub fn open<A>(
+    addr: A,
+    transport: Transport,
+    options: &SendOptions,
+  ) -> Result<Self, std::io::Error>
+  where
+    A: ToSocketAddrs,
+  {
+    let framing = match transport {
+      Transport::Udp => None,
+      Transport::Tcp(framing) => Some(framing),
+    };
+    let mut last_error = None;
+    for addr in addr.to_socket_addrs()? {
+      let connection = match framing {
+        None => {
+          let options = SendOptions {
+            broadcast: options.broadcast || addr.ip() == IpAddr::V4(Ipv4Addr::BROADCAST),
+            ..*options
+          };
+          Sender::bind_to(send::default_sender_socket_addr_for(&addr))
+            .and_then(|sender| sender.configure(&options).map(|_| sender))
+            .and_then(|sender| sender.connect(addr))
+            .map(Connection::Udp)
+        }
+        Some(framing) => tcp::Sender::connect(addr, framing).map(Connection::Tcp),
+      };
+      match connection {
+        Ok(connection) => return Ok(connection),
+        Err(e) => last_error = Some(e),
+      }
+    }
+    Err(last_error.unwrap_or_else(|| {
+      std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "the address did not resolve to any IP address",
+      )
+    }))
+  }
+
+  /// Returns the address the connection sends to.
+  pub fn remote_addr(&self) -> SocketAddr {
+    match self {
+      Connection::Udp(sender) => sender.remote_addr(),
+      Connection::Tcp(sender) => sender.remote_addr(),
+    }
+  }
+
+  /// Sets how long `recv_bytes` may block, `None` blocks indefinitely.
+  pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error> {
+    match self {
+      Connection::Udp(sender) => sender.set_read_timeout(timeout),
+      Connection::Tcp(sender) => sender.set_read_timeout(timeout),
+    }
+  }
+
+  /// Sends the given packet, returning the number of bytes of the encoded packet.
+  pub fn send<P>(&self, packet: P) -> Result<usize, CommunicationError>
+  where
+    P: Into<Packet>,
+  {
+    match self {
+      Connection::Udp(sender) => sender.send(packet),
+      Connection::Tcp(sender) => sender.send(packet),
+    }
+  }
+
+  /// Sends already encoded bytes as-is, framed when sent over TCP.
+  pub fn send_bytes(&self, bytes: &[u8]) -> Result<usize, CommunicationError> {
+    match self {
+      Connection::Udp(sender) => sender.send_bytes(bytes),
+      Connection::Tcp(sender) => sender.send_bytes(bytes),
+    }
+  }
+
+  /// Blocks until a packet arrives from the target and returns its bytes.
+  pub fn recv_bytes(&self) -> Result<Vec<u8>, CommunicationError> {
+    match self {
+      Connection::Udp(sender) => sender.recv_bytes(),
+      Connection::Tcp(sender) => sender.recv_bytes(),
+    }
+  }
+}
+
 /// An *OSC packet* can contain an OSC message or a bundle of nested packets which is called an
 /// *OSC bundle*.
 #[derive(Clone, Debug, PartialEq)]
@@ -114,6 +226,24 @@ pub fn default_ipv4_addr() -> Ipv4Addr {
   Ipv4Addr::new(0, 0, 0, 0)
 }
 
+/// The default local IPv6 address. Listening on it also receives IPv4 wherever the OS makes
+/// IPv6 sockets dual-stack, as Linux does by default.
+pub fn default_ipv6_addr() -> Ipv6Addr {
+  Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)
+}
+
+/// Turns an IPv4 address mapped into IPv6 (eg. `[::ffff:10.0.0.5]:9000`), as dual-stack sockets
+/// report IPv4 peers, back into the IPv4 address it stands for.
+pub fn unmapped(addr: SocketAddr) -> SocketAddr {
+  match addr {
+    SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+      Some(ip) => SocketAddr::new(ip.into(), v6.port()),
+      None => addr,
+    },
+    addr => addr,
+  }
+}
+
 /// A simple wrapper around the most commonly used `Receiver` constructor.
 pub fn receiver(port: u16) -> Result<Receiver, std::io::Error> {
   Receiver::bind(port)
@@ -198,3 +328,23 @@ impl std::fmt::Display for CommunicationError {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_unmapped_addresses() {
+    let addrs = [
+      ("[::ffff:10.0.0.5]:9000", "10.0.0.5:9000"),
+      ("[::1]:9000", "[::1]:9000"),
+      ("[fe80::1%2]:9000", "[fe80::1%2]:9000"),
+      ("10.0.0.5:9000", "10.0.0.5:9000"),
+    ];
+
+    for (addr, expected) in addrs.iter() {
+      let addr = addr.parse::<SocketAddr>().unwrap();
+      assert_eq!(unmapped(addr), expected.parse::<SocketAddr>().unwrap());
+    }
+  }
+}
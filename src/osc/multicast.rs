@@ -0,0 +1,124 @@
+//! Items related to multicast groups and to sending to multicast or broadcast addresses.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// A multicast group to join, along with the local interface to join it on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+  pub addr: IpAddr,
+  pub interface: Interface,
+}
+
+/// The local interface a group is joined on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interface {
+  /// Left for the OS to choose.
+  Default,
+  /// An IPv4 interface, by its address.
+  V4(Ipv4Addr),
+  /// An IPv6 interface, by its index, eg. as listed by `ip link`.
+  Index(u32),
+}
+
+impl Group {
+  /// Parses `239.1.2.3`, `239.1.2.3@192.168.1.10` (joined on the interface with that address) or
+  /// `ff12::1234@2` (joined on the interface with index 2).
+  pub fn parse(group: &str) -> Result<Group, String> {
+    let (addr, interface) = match group.split_once('@') {
+      Some((addr, interface)) => (addr, Some(interface)),
+      None => (group, None),
+    };
+    let addr = addr
+      .trim_start_matches('[')
+      .trim_end_matches(']')
+      .parse::<IpAddr>()
+      .map_err(|_| format!("`{}` is not an IP address", addr))?;
+    if !addr.is_multicast() {
+      return Err(format!(
+        "`{}` is not a multicast address, eg. 239.1.2.3 or ff12::1234",
+        addr
+      ));
+    }
+    let interface = match (addr, interface) {
+      (_, None) => Interface::Default,
+      (IpAddr::V4(_), Some(interface)) => interface
+        .parse::<Ipv4Addr>()
+        .map(Interface::V4)
+        .map_err(|_| format!("`{}` should be the IPv4 address of an interface", interface))?,
+      (IpAddr::V6(_), Some(interface)) => interface
+        .parse::<u32>()
+        .map(Interface::Index)
+        .map_err(|_| format!("`{}` should be the index of an interface, eg. 2", interface))?,
+    };
+    Ok(Group { addr, interface })
+  }
+}
+
+impl fmt::Display for Group {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.interface {
+      Interface::Default => write!(f, "{}", self.addr),
+      Interface::V4(ip) => write!(f, "{}@{}", self.addr, ip),
+      Interface::Index(index) => write!(f, "{}@{}", self.addr, index),
+    }
+  }
+}
+
+/// Socket options of UDP senders.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SendOptions {
+  /// Allows sending to broadcast addresses (`SO_BROADCAST`), always set for `255.255.255.255`.
+  pub broadcast: bool,
+  /// How many routers multicast packets may cross, the OS default (1) when `None`. IPv4 only,
+  /// IPv6 senders fail to be configured with one.
+  pub ttl: Option<u32>,
+  /// Whether multicast packets are also delivered to the sending host.
+  pub loopback: bool,
+}
+
+impl Default for SendOptions {
+  fn default() -> Self {
+    SendOptions {
+      broadcast: false,
+      ttl: None,
+      loopback: true,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_groups() {
+    let groups = [
+      ("239.1.2.3", Interface::Default),
+      (
+        "239.1.2.3@192.168.1.10",
+        Interface::V4(Ipv4Addr::new(192, 168, 1, 10)),
+      ),
+      ("ff12::1234@2", Interface::Index(2)),
+      ("[ff12::1234]", Interface::Default),
+    ];
+
+    for (group, interface) in groups.iter() {
+      let parsed = Group::parse(group).unwrap();
+      assert_eq!(parsed.interface, *interface, "{}", group);
+      assert_eq!(
+        Group::parse(&parsed.to_string()).unwrap(),
+        parsed,
+        "{}",
+        group
+      );
+    }
+  }
+
+  #[test]
+  fn test_invalid_groups() {
+    for group in ["10.0.0.5", "239.1.2.3@eth0", "ff12::1234@fe80::1", "nope"].iter() {
+      assert!(Group::parse(group).is_err(), "{}", group);
+    }
+  }
+}
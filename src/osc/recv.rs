@@ -1,8 +1,9 @@
 //! Items related to the `osc::Receiver` implementation.
 
+use super::multicast::{Group, Interface};
 use super::{decode, rosc, CommunicationError, Connected, Packet, Unconnected};
 use std;
-use std::net::{SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket};
 use std::sync::atomic::{self, AtomicBool};
 use std::sync::Mutex;
 
@@ -10,6 +11,10 @@ use std::sync::Mutex;
 ///
 /// This is a common MTU size for ethernet.
 pub const DEFAULT_MTU: usize = rosc::decoder::MTU;
+/// The largest payload a single UDP datagram can carry over IPv4.
+///
+/// Using this as the MTU guarantees that no datagram is ever truncated by the receiving buffer.
+pub const MAX_UDP_PAYLOAD: usize = 65_507;
 /// By default UDP sockets are blocking so this is the mode in which the receiver is
 /// initialised.
 pub const DEFAULT_NON_BLOCKING: bool = false;
@@ -58,6 +63,19 @@ impl<M> Receiver<M> {
     self.socket.local_addr()
   }
 
+  /// Joins the given multicast group, the socket has to be bound to an unspecified address (or
+  /// the group address itself) of the same IP family.
+  pub fn join(&self, group: &Group) -> Result<(), std::io::Error> {
+    match (group.addr, group.interface) {
+      (IpAddr::V4(addr), Interface::V4(interface)) => {
+        self.socket.join_multicast_v4(&addr, &interface)
+      }
+      (IpAddr::V4(addr), _) => self.socket.join_multicast_v4(&addr, &Ipv4Addr::UNSPECIFIED),
+      (IpAddr::V6(addr), Interface::Index(index)) => self.socket.join_multicast_v6(&addr, index),
+      (IpAddr::V6(addr), _) => self.socket.join_multicast_v6(&addr, 0),
+    }
+  }
+
   // Switch the `Receiver`'s inner socket to blocking mode.
   // This is for internal use only - the `recv` methods will call this automatically.
   fn switch_to_blocking(&self) -> Result<(), std::io::Error> {
@@ -219,6 +237,24 @@ impl Receiver<Unconnected> {
     Ok((packet, addr))
   }
 
+  /// Waits for the next datagram and returns its raw bytes along with the source address,
+  /// leaving decoding up to the caller.
+  ///
+  /// This is useful when the bytes themselves are of interest, eg. to inspect a datagram that
+  /// fails to decode or to know the exact size of what was received.
+  ///
+  /// This will return a `CommunicationError` if:
+  ///
+  /// - Switching the socket from "non_blocking" to "blocking" fails,
+  /// - The Mutex around the inner buffer (used to collect bytes) was poisoned,
+  /// - The inner `UdpSocket::recv` call fails.
+  pub fn recv_bytes(&self) -> Result<(Vec<u8>, SocketAddr), CommunicationError> {
+    self.switch_to_blocking()?;
+    let mut buffer = self.buffer.lock()?;
+    let (len, addr) = self.socket.recv_from(&mut buffer)?;
+    Ok((buffer[..len].to_vec(), addr))
+  }
+
   /// Checks for a pending OSC packet and returns `Ok(Some)` if there is one waiting along with
   /// the source address.
   ///
@@ -265,6 +301,15 @@ impl Receiver<Unconnected> {
   pub fn try_iter(&self) -> TryIter<Unconnected> {
     TryIter { receiver: self }
   }
+
+  /// Sends already encoded bytes to `addr` from the `Receiver`'s socket, as-is, eg. to answer a
+  /// sender from the port it sent to.
+  ///
+  /// On success, returns the number of bytes written.
+  pub fn send_bytes_to(&self, bytes: &[u8], addr: SocketAddr) -> Result<usize, CommunicationError> {
+    let bytes_written = self.socket.send_to(bytes, addr)?;
+    Ok(bytes_written)
+  }
 }
 
 impl Receiver<Connected> {
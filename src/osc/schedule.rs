@@ -0,0 +1,205 @@
+//! Items related to the `osc::Scheduler`, which holds packets back and sends them on time.
+
+use super::{CommunicationError, Connection, Packet};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long before a packet is due the scheduler stops sleeping and spins instead, sleeping is
+/// not precise enough to hit the due time to the tenth of a millisecond.
+pub const SPIN_AHEAD: Duration = Duration::from_millis(2);
+
+/// A packet the scheduler sent, or failed to send.
+#[derive(Debug)]
+pub struct Dispatch {
+  pub packet: Packet,
+  /// How long after it was due the packet went out.
+  pub jitter: Duration,
+  pub result: Result<usize, CommunicationError>,
+}
+
+/// Sends packets through a `Connection` at a given point in time rather than as soon as possible,
+/// eg. bundles at the time of their timetag for receivers that ignore timetags.
+///
+/// Packets are held and sent by a background thread, in the order they are due. Every packet
+/// sent is handed over to the `on_dispatch` callback given to `Scheduler::new`.
+pub struct Scheduler {
+  queue: Option<mpsc::Sender<Pending>>,
+  worker: Option<JoinHandle<()>>,
+}
+
+struct Pending {
+  packet: Packet,
+  due: Instant,
+  // Packets due at the same time are sent in the order they were scheduled.
+  order: u64,
+}
+
+impl Scheduler {
+  /// Starts the background thread sending through `sender`.
+  pub fn new<F>(sender: Arc<Connection>, on_dispatch: F) -> Self
+  where
+    F: FnMut(Dispatch) + Send + 'static,
+  {
+    let (queue, pending) = mpsc::channel();
+    let worker = thread::spawn(move || run(sender, pending, on_dispatch));
+    Scheduler {
+      queue: Some(queue),
+      worker: Some(worker),
+    }
+  }
+
+  /// Sends `packet` at `at`, or right away if that time has already passed.
+  pub fn schedule(&self, packet: Packet, at: SystemTime) {
+    let now = Instant::now();
+    let due = match at.duration_since(SystemTime::now()) {
+      Ok(ahead) => now + ahead,
+      Err(_) => now,
+    };
+    if let Some(queue) = &self.queue {
+      // Only fails once the worker is gone, in which case there is nobody left to send anyway.
+      let _ = queue.send(Pending {
+        packet,
+        due,
+        order: 0,
+      });
+    }
+  }
+
+  /// Blocks until every scheduled packet has been sent.
+  pub fn finish(mut self) {
+    self.queue.take();
+    if let Some(worker) = self.worker.take() {
+      let _ = worker.join();
+    }
+  }
+}
+
+fn run<F>(sender: Arc<Connection>, pending: mpsc::Receiver<Pending>, mut on_dispatch: F)
+where
+  F: FnMut(Dispatch),
+{
+  let mut heap = BinaryHeap::new();
+  let mut order = 0;
+  let mut open = true;
+  while open || !heap.is_empty() {
+    // Wait for new packets until the next one is almost due.
+    let next = heap.peek().map(|next: &Pending| next.due);
+    let received = match next {
+      Some(due) if !open => {
+        let wait = due.saturating_duration_since(Instant::now());
+        thread::sleep(wait.saturating_sub(SPIN_AHEAD));
+        None
+      }
+      Some(due) => {
+        let wait = due.saturating_duration_since(Instant::now());
+        match pending.recv_timeout(wait.saturating_sub(SPIN_AHEAD)) {
+          Ok(packet) => Some(packet),
+          Err(RecvTimeoutError::Timeout) => None,
+          Err(RecvTimeoutError::Disconnected) => {
+            open = false;
+            None
+          }
+        }
+      }
+      None => match pending.recv() {
+        Ok(packet) => Some(packet),
+        Err(_) => {
+          open = false;
+          None
+        }
+      },
+    };
+    if let Some(mut packet) = received {
+      order += 1;
+      packet.order = order;
+      heap.push(packet);
+      continue;
+    }
+
+    let due = match heap.peek() {
+      Some(next) if next.due.saturating_duration_since(Instant::now()) <= SPIN_AHEAD => next.due,
+      _ => continue,
+    };
+    while Instant::now() < due {
+      std::hint::spin_loop();
+    }
+    if let Some(Pending { packet, due, .. }) = heap.pop() {
+      let result = sender.send(packet.clone());
+      on_dispatch(Dispatch {
+        packet,
+        jitter: Instant::now().saturating_duration_since(due),
+        result,
+      });
+    }
+  }
+}
+
+impl PartialEq for Pending {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+// `BinaryHeap` is a max-heap, the packet due first has to compare as the greatest.
+impl Ord for Pending {
+  fn cmp(&self, other: &Self) -> Ordering {
+    (other.due, other.order).cmp(&(self.due, self.order))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::{decode, msg, SendOptions, Transport, Type};
+  use super::*;
+  use std::net::UdpSocket;
+  use std::sync::Mutex;
+
+  #[test]
+  fn test_sends_in_due_order() {
+    let target = UdpSocket::bind("127.0.0.1:0").unwrap();
+    target
+      .set_read_timeout(Some(Duration::from_secs(2)))
+      .unwrap();
+    let options = SendOptions::default();
+    let sender = Connection::open(target.local_addr().unwrap(), Transport::Udp, &options).unwrap();
+    let dispatched = Arc::new(Mutex::new(vec![]));
+    let log = Arc::clone(&dispatched);
+    let scheduler = Scheduler::new(Arc::new(sender), move |dispatch: Dispatch| {
+      log.lock().unwrap().push(dispatch)
+    });
+
+    let start = SystemTime::now();
+    let later = msg("/later", vec![Type::Int(2)]);
+    let sooner = msg("/sooner", vec![Type::Int(1)]);
+    let past = msg("/past", vec![]);
+    scheduler.schedule(later.clone().into(), start + Duration::from_millis(60));
+    scheduler.schedule(sooner.clone().into(), start + Duration::from_millis(30));
+    scheduler.schedule(past.clone().into(), start - Duration::from_secs(1));
+    scheduler.finish();
+
+    let mut buffer = [0; 1024];
+    let mut received = vec![];
+    for _ in 0..3 {
+      let len = target.recv(&mut buffer).unwrap();
+      received.push(decode(&buffer[..len]).unwrap());
+    }
+    assert_eq!(received, vec![past.into(), sooner.into(), later.into()]);
+
+    let dispatched = dispatched.lock().unwrap();
+    assert_eq!(dispatched.len(), 3);
+    assert!(dispatched.iter().all(|d| d.result.is_ok()));
+    assert!(start.elapsed().unwrap() >= Duration::from_millis(60));
+  }
+}
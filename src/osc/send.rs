@@ -1,8 +1,10 @@
 //! Items related to the `osc::Sender` implementation.
 
+use super::multicast::SendOptions;
 use super::{encode, CommunicationError, Connected, Packet, Unconnected};
 use std;
-use std::net::{SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket};
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
 
 /// The default port bound to by the `Sender`.
 ///
@@ -23,11 +25,46 @@ pub fn default_sender_socket_addr_v4() -> SocketAddrV4 {
   SocketAddrV4::new(super::default_ipv4_addr(), DEFAULT_PORT)
 }
 
+/// The socket address a `Sender` sending to `target` binds to, the unspecified address of the
+/// same IP family with the `DEFAULT_PORT`.
+pub fn default_sender_socket_addr_for(target: &SocketAddr) -> SocketAddr {
+  match target {
+    SocketAddr::V4(_) => default_sender_socket_addr_v4().into(),
+    SocketAddr::V6(_) => SocketAddrV6::new(super::default_ipv6_addr(), DEFAULT_PORT, 0, 0).into(),
+  }
+}
+
 impl<M> Sender<M> {
   /// The socket address that this `Sender`'s socket was created from.
   pub fn local_addr(&self) -> Result<SocketAddr, std::io::Error> {
     self.socket.local_addr()
   }
+
+  /// Applies the given broadcast and multicast options to the `Sender`'s socket.
+  ///
+  /// Broadcast has to be allowed before connecting to a broadcast address. A `ttl` cannot be
+  /// applied to IPv6 sockets.
+  pub fn configure(&self, options: &SendOptions) -> Result<(), std::io::Error> {
+    self.socket.set_broadcast(options.broadcast)?;
+    match self.socket.local_addr()? {
+      SocketAddr::V4(_) => {
+        if let Some(ttl) = options.ttl {
+          self.socket.set_multicast_ttl_v4(ttl)?;
+        }
+        self.socket.set_multicast_loop_v4(options.loopback)
+      }
+      SocketAddr::V6(_) if options.ttl.is_some() => Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "the hop limit of IPv6 multicast packets cannot be set",
+      )),
+      SocketAddr::V6(_) => self.socket.set_multicast_loop_v6(options.loopback),
+    }
+  }
+
+  /// Sets how long receiving on the `Sender`'s socket may block, `None` blocks indefinitely.
+  pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error> {
+    self.socket.set_read_timeout(timeout)
+  }
 }
 
 impl Sender<Unconnected> {
@@ -142,4 +179,23 @@ impl Sender<Connected> {
     let bytes_written = self.socket.send(&bytes)?;
     Ok(bytes_written)
   }
+
+  /// Sends already encoded bytes on the `Sender`s socket to the connected address, as-is.
+  ///
+  /// On success, returns the number of bytes written.
+  pub fn send_bytes(&self, bytes: &[u8]) -> Result<usize, CommunicationError> {
+    let bytes_written = self.socket.send(bytes)?;
+    Ok(bytes_written)
+  }
+
+  /// Blocks until a datagram arrives from the connected address and returns its bytes.
+  ///
+  /// A connected socket only receives from the address it is connected to, so this can be used
+  /// from another thread to listen for replies, eg. SuperCollider's `/done` or `/fail`.
+  pub fn recv_bytes(&self) -> Result<Vec<u8>, CommunicationError> {
+    let mut buffer = vec![0; super::recv::MAX_UDP_PAYLOAD];
+    let len = self.socket.recv(&mut buffer)?;
+    buffer.truncate(len);
+    Ok(buffer)
+  }
 }
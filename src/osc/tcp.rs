@@ -0,0 +1,668 @@
+//! Items related to sending and receiving OSC packets over TCP.
+//!
+//! TCP is a stream, packets have to be framed to be told apart. OSC 1.0 prefixes every packet
+//! with its size as a big-endian int32, OSC 1.1 delimits them with SLIP (RFC 1055) instead.
+
+use super::{encode, CommunicationError, Packet};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The largest frame accepted, a peer announcing a larger one is most likely not speaking the
+/// expected framing.
+pub const MAX_FRAME: usize = 16 * 1024 * 1024;
+/// How many bytes are read from the stream at once.
+pub const READ_SIZE: usize = 64 * 1024;
+
+// How long accepting connections pauses after a failure, doubled after every failure in a row, eg.
+// while the process is out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+const SLIP_END: u8 = 0xc0;
+const SLIP_ESC: u8 = 0xdb;
+const SLIP_ESC_END: u8 = 0xdc;
+const SLIP_ESC_ESC: u8 = 0xdd;
+
+/// How packets are delimited on a TCP stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Framing {
+  /// OSC 1.1, every packet is SLIP encoded and terminated by a `0xc0` byte.
+  Slip,
+  /// OSC 1.0, every packet is preceded by its size as a big-endian int32.
+  LengthPrefix,
+  /// Detected from the first bytes of each connection when receiving, see `Deframer::detect`.
+  /// Sending uses SLIP.
+  Auto,
+}
+
+impl FromStr for Framing {
+  type Err = String;
+
+  fn from_str(framing: &str) -> Result<Framing, String> {
+    match framing {
+      "slip" => Ok(Framing::Slip),
+      "length" => Ok(Framing::LengthPrefix),
+      "auto" => Ok(Framing::Auto),
+      _ => Err(format!("`{}` should be one of slip, length, auto", framing)),
+    }
+  }
+}
+
+impl fmt::Display for Framing {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Framing::Slip => write!(f, "slip"),
+      Framing::LengthPrefix => write!(f, "length"),
+      Framing::Auto => write!(f, "auto"),
+    }
+  }
+}
+
+/// Frames the encoded bytes of a packet to be written to a stream.
+///
+/// SLIP frames also start with a `0xc0` byte, flushing whatever line noise preceded them on the
+/// receiving end, as recommended by OSC 1.1.
+pub fn frame(framing: Framing, bytes: &[u8]) -> Vec<u8> {
+  match framing {
+    Framing::Slip | Framing::Auto => {
+      let mut framed = Vec::with_capacity(bytes.len() + 2);
+      framed.push(SLIP_END);
+      for &byte in bytes {
+        match byte {
+          SLIP_END => framed.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+          SLIP_ESC => framed.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+          byte => framed.push(byte),
+        }
+      }
+      framed.push(SLIP_END);
+      framed
+    }
+    Framing::LengthPrefix => {
+      let mut framed = (bytes.len() as u32).to_be_bytes().to_vec();
+      framed.extend_from_slice(bytes);
+      framed
+    }
+  }
+}
+
+/// Splits the bytes read from a stream back into frames, whatever the reads boundaries are.
+#[derive(Debug)]
+pub struct Deframer {
+  framing: Framing,
+  // Set once detection found neither framing, every read is then a frame of its own.
+  raw: bool,
+  // The frame being read, unescaped already for SLIP, including the size prefix otherwise.
+  // While detecting, the bytes read so far.
+  partial: Vec<u8>,
+  escaped: bool,
+  frames: VecDeque<Vec<u8>>,
+}
+
+impl Deframer {
+  pub fn new(framing: Framing) -> Self {
+    Deframer {
+      framing,
+      raw: false,
+      partial: vec![],
+      escaped: false,
+      frames: VecDeque::new(),
+    }
+  }
+
+  /// Feeds the next bytes read from the stream, the frames they complete are returned by
+  /// `next_frame`.
+  ///
+  /// Returns an `InvalidData` error if a frame is larger than `MAX_FRAME`, the stream cannot be
+  /// trusted to be in sync anymore and should be closed.
+  pub fn push(&mut self, bytes: &[u8]) -> Result<(), io::Error> {
+    match self.framing {
+      _ if self.raw => {
+        if !bytes.is_empty() {
+          self.frames.push_back(bytes.to_vec());
+        }
+        Ok(())
+      }
+      Framing::Slip => self.push_slip(bytes),
+      Framing::LengthPrefix => self.push_length_prefixed(bytes),
+      Framing::Auto => self.push_detect(bytes),
+    }
+  }
+
+  /// The framing in use, `Auto` until enough bytes were read to detect it, `None` if neither
+  /// framing fits and the bytes are passed on as they were read.
+  pub fn framing(&self) -> Option<Framing> {
+    match self.raw {
+      true => None,
+      false => Some(self.framing),
+    }
+  }
+
+  /// Pops the oldest complete frame, if any.
+  pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+    self.frames.pop_front()
+  }
+
+  /// Returns an `UnexpectedEof` error if the stream ended in the middle of a frame.
+  ///
+  /// Bytes too few to detect the framing from are passed on as a raw frame.
+  pub fn finish(&mut self) -> Result<(), io::Error> {
+    if self.framing == Framing::Auto && !self.partial.is_empty() {
+      self.raw = true;
+      self.frames.push_back(std::mem::take(&mut self.partial));
+    }
+    match self.partial.len() {
+      0 => Ok(()),
+      len => Err(io::Error::new(
+        ErrorKind::UnexpectedEof,
+        format!(
+          "connection closed in the middle of a frame, {} bytes lost",
+          len
+        ),
+      )),
+    }
+  }
+
+  // A SLIP stream starts with `0xc0`, or directly with the `/` or `#` of a packet when senders
+  // only end frames with `0xc0`. None of these can start a size prefix, since they would announce
+  // more than `MAX_FRAME` bytes. A length-prefixed stream is recognised by a size that is a
+  // multiple of 4 followed by the start of a packet.
+  fn push_detect(&mut self, bytes: &[u8]) -> Result<(), io::Error> {
+    self.partial.extend_from_slice(bytes);
+    let detected = match self.partial.first() {
+      None => return Ok(()),
+      Some(&SLIP_END) | Some(b'/') | Some(b'#') => Some(Framing::Slip),
+      Some(_) if self.partial.len() < 5 => return Ok(()),
+      Some(_) => match self.announced_size() {
+        Some(size) if size <= MAX_FRAME && size % 4 == 0 && b"/#".contains(&self.partial[4]) => {
+          Some(Framing::LengthPrefix)
+        }
+        _ => None,
+      },
+    };
+    let buffered = std::mem::take(&mut self.partial);
+    match detected {
+      Some(framing) => self.framing = framing,
+      None => self.raw = true,
+    }
+    self.push(&buffered)
+  }
+
+  fn push_slip(&mut self, bytes: &[u8]) -> Result<(), io::Error> {
+    for &byte in bytes {
+      match (self.escaped, byte) {
+        (false, SLIP_END) => {
+          // Back to back `0xc0` delimit empty frames, which are skipped.
+          if !self.partial.is_empty() {
+            self.frames.push_back(std::mem::take(&mut self.partial));
+          }
+        }
+        (false, SLIP_ESC) => self.escaped = true,
+        (false, byte) => self.partial.push(byte),
+        (true, byte) => {
+          self.escaped = false;
+          // RFC 1055 leaves an invalid escape as it is.
+          self.partial.push(match byte {
+            SLIP_ESC_END => SLIP_END,
+            SLIP_ESC_ESC => SLIP_ESC,
+            byte => byte,
+          });
+        }
+      }
+      if self.partial.len() > MAX_FRAME {
+        return Err(too_large(self.partial.len()));
+      }
+    }
+    Ok(())
+  }
+
+  fn push_length_prefixed(&mut self, mut bytes: &[u8]) -> Result<(), io::Error> {
+    while !bytes.is_empty() {
+      let wanted = match self.announced_size() {
+        Some(size) => 4 + size - self.partial.len(),
+        None => 4 - self.partial.len(),
+      };
+      let (head, rest) = bytes.split_at(wanted.min(bytes.len()));
+      self.partial.extend_from_slice(head);
+      bytes = rest;
+      match self.announced_size() {
+        Some(size) if size > MAX_FRAME => return Err(too_large(size)),
+        Some(size) if self.partial.len() == 4 + size => {
+          let frame = self.partial.split_off(4);
+          self.partial.clear();
+          // A zero size frame carries no packet.
+          if !frame.is_empty() {
+            self.frames.push_back(frame);
+          }
+        }
+        _ => (),
+      }
+    }
+    Ok(())
+  }
+
+  // The size of the frame being read, once its prefix was read entirely.
+  fn announced_size(&self) -> Option<usize> {
+    self
+      .partial
+      .get(..4)
+      .map(|prefix| u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize)
+  }
+}
+
+fn too_large(size: usize) -> io::Error {
+  io::Error::new(
+    ErrorKind::InvalidData,
+    format!(
+      "frame of {} bytes is larger than the {} bytes allowed, is the framing right?",
+      size, MAX_FRAME
+    ),
+  )
+}
+
+/// What happened on one of the connections accepted by a `Listener`.
+#[derive(Debug)]
+pub enum Event {
+  Connected(SocketAddr),
+  /// The framing detected on a connection accepted with `Framing::Auto`, `None` when neither
+  /// framing fits and frames are the bytes as they were read.
+  Detected(SocketAddr, Option<Framing>),
+  /// The bytes of a complete frame, along with the address of the peer that sent it.
+  Frame(Vec<u8>, SocketAddr),
+  /// The connection was closed, by the peer or because of the given error.
+  Closed(SocketAddr, Option<io::Error>),
+  /// A connection could not be accepted, eg. because the process ran out of file descriptors.
+  /// Accepting goes on after a pause.
+  AcceptFailed(io::Error),
+}
+
+/// Accepts TCP connections and reads the frames sent over any of them.
+pub struct Listener {
+  local_addr: SocketAddr,
+  events: Mutex<mpsc::Receiver<Event>>,
+}
+
+impl Listener {
+  /// Listens on `addr`, every accepted connection is read from its own thread.
+  pub fn bind<A>(addr: A, framing: Framing) -> Result<Self, io::Error>
+  where
+    A: ToSocketAddrs,
+  {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let (events, receiver) = mpsc::channel();
+    thread::spawn(move || {
+      let mut backoff = ACCEPT_BACKOFF;
+      for stream in listener.incoming() {
+        match stream {
+          Ok(stream) => {
+            backoff = ACCEPT_BACKOFF;
+            let events = events.clone();
+            thread::spawn(move || read_frames(stream, framing, events));
+          }
+          Err(e) => {
+            if events.send(Event::AcceptFailed(e)).is_err() {
+              break;
+            }
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+          }
+        }
+      }
+    });
+    Ok(Listener {
+      local_addr,
+      events: Mutex::new(receiver),
+    })
+  }
+
+  /// The socket address the `Listener` accepts connections on.
+  pub fn local_addr(&self) -> SocketAddr {
+    self.local_addr
+  }
+
+  /// Waits for something to happen on any of the connections.
+  pub fn recv(&self) -> Result<Event, CommunicationError> {
+    let events = self.events.lock()?;
+    events.recv().map_err(|_| {
+      CommunicationError::Io(io::Error::new(
+        ErrorKind::BrokenPipe,
+        "stopped accepting connections",
+      ))
+    })
+  }
+}
+
+fn read_frames(mut stream: TcpStream, framing: Framing, events: mpsc::Sender<Event>) {
+  let source = match stream.peer_addr() {
+    Ok(source) => super::unmapped(source),
+    Err(_) => return,
+  };
+  if events.send(Event::Connected(source)).is_err() {
+    return;
+  }
+  let mut deframer = Deframer::new(framing);
+  let mut detecting = framing == Framing::Auto;
+  let mut buffer = vec![0; READ_SIZE];
+  let error = loop {
+    let (result, closed) = match stream.read(&mut buffer) {
+      Ok(0) => (deframer.finish(), true),
+      Ok(len) => (deframer.push(&buffer[..len]), false),
+      Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+      Err(e) => break Some(e),
+    };
+    if detecting && deframer.framing() != Some(Framing::Auto) {
+      detecting = false;
+      if events
+        .send(Event::Detected(source, deframer.framing()))
+        .is_err()
+      {
+        return;
+      }
+    }
+    while let Some(frame) = deframer.next_frame() {
+      if events.send(Event::Frame(frame, source)).is_err() {
+        return;
+      }
+    }
+    if let Err(e) = result {
+      let _ = stream.shutdown(Shutdown::Both);
+      break Some(e);
+    }
+    if closed {
+      break None;
+    }
+  };
+  let _ = events.send(Event::Closed(source, error));
+}
+
+/// Sends framed packets over a TCP connection, reconnecting when it was closed.
+pub struct Sender {
+  addr: SocketAddr,
+  framing: Framing,
+  stream: Mutex<Option<TcpStream>>,
+  reader: Mutex<Reader>,
+}
+
+// The reading side of the connection, kept apart so that waiting for a reply does not prevent
+// sending.
+struct Reader {
+  stream: Option<TcpStream>,
+  deframer: Deframer,
+  timeout: Option<Duration>,
+}
+
+impl Sender {
+  /// Connects to `addr`.
+  pub fn connect<A>(addr: A, framing: Framing) -> Result<Self, io::Error>
+  where
+    A: ToSocketAddrs,
+  {
+    let stream = TcpStream::connect(addr)?;
+    let addr = stream.peer_addr()?;
+    stream.set_nodelay(true)?;
+    Ok(Sender {
+      addr,
+      framing,
+      stream: Mutex::new(Some(stream)),
+      reader: Mutex::new(Reader {
+        stream: None,
+        deframer: Deframer::new(framing),
+        timeout: None,
+      }),
+    })
+  }
+
+  /// Returns the address the `Sender` is connected to.
+  pub fn remote_addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// Sets how long waiting for a reply may block, `None` blocks indefinitely.
+  pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), io::Error> {
+    let mut reader = self.reader.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(stream) = &reader.stream {
+      stream.set_read_timeout(timeout)?;
+    }
+    reader.timeout = timeout;
+    Ok(())
+  }
+
+  /// Encodes and sends the given packet, see `send_bytes`.
+  pub fn send<P>(&self, packet: P) -> Result<usize, CommunicationError>
+  where
+    P: Into<Packet>,
+  {
+    let bytes = encode(packet.into())?;
+    self.send_bytes(&bytes)
+  }
+
+  /// Frames and sends already encoded bytes.
+  ///
+  /// If the connection was closed in the meantime, eg. because the receiver restarted, it is
+  /// opened again and the bytes are sent once more. A closed connection is only noticed once
+  /// writing to it fails though, which usually takes a write or two after the peer is gone: the
+  /// packets written until then are lost. On success, returns the number of bytes of the packet,
+  /// without framing.
+  pub fn send_bytes(&self, bytes: &[u8]) -> Result<usize, CommunicationError> {
+    let framed = frame(self.framing, bytes);
+    let mut stream = self.stream.lock()?;
+    if let Some(current) = stream.as_mut() {
+      if current.write_all(&framed).is_ok() {
+        return Ok(bytes.len());
+      }
+      let _ = current.shutdown(Shutdown::Both);
+    }
+    *stream = None;
+    let mut reconnected = TcpStream::connect(self.addr)?;
+    reconnected.set_nodelay(true)?;
+    reconnected.write_all(&framed)?;
+    *stream = Some(reconnected);
+    Ok(bytes.len())
+  }
+
+  /// Blocks until a complete frame arrives on the connection and returns its bytes.
+  ///
+  /// Returns an `UnexpectedEof` error once the connection was closed by the receiver, and a
+  /// `NotConnected` error until sending opened it again.
+  pub fn recv_bytes(&self) -> Result<Vec<u8>, CommunicationError> {
+    let mut reader = self.reader.lock()?;
+    let mut buffer = vec![0; READ_SIZE];
+    loop {
+      if let Some(frame) = reader.deframer.next_frame() {
+        return Ok(frame);
+      }
+      if reader.stream.is_none() {
+        let cloned = match self.stream.lock()?.as_ref() {
+          Some(stream) => stream.try_clone()?,
+          None => return Err(io::Error::from(ErrorKind::NotConnected).into()),
+        };
+        cloned.set_read_timeout(reader.timeout)?;
+        reader.stream = Some(cloned);
+        reader.deframer = Deframer::new(self.framing);
+      }
+      let read = match reader.stream.as_mut() {
+        Some(stream) => stream.read(&mut buffer),
+        None => continue,
+      };
+      match read {
+        Ok(0) => {
+          self.disconnect(reader.stream.take())?;
+          return Err(
+            io::Error::new(
+              ErrorKind::UnexpectedEof,
+              format!("connection closed by {}", self.addr),
+            )
+            .into(),
+          );
+        }
+        Ok(len) => {
+          if let Err(e) = reader.deframer.push(&buffer[..len]) {
+            self.disconnect(reader.stream.take())?;
+            return Err(e.into());
+          }
+        }
+        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+        Err(e) => {
+          if !matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+            reader.stream = None;
+          }
+          return Err(e.into());
+        }
+      }
+    }
+  }
+
+  // Drops the connection `read` was cloned from so that the next send opens a new one, unless
+  // sending did so already. `None` drops whatever connection is open.
+  fn disconnect(&self, read: Option<TcpStream>) -> Result<(), CommunicationError> {
+    let mut stream = self.stream.lock()?;
+    let local_addr = |stream: &TcpStream| stream.local_addr().ok();
+    let same = match (stream.as_ref(), read.as_ref()) {
+      (Some(current), Some(read)) => local_addr(current) == local_addr(read),
+      (_, None) => true,
+      (None, _) => false,
+    };
+    if same {
+      if let Some(current) = stream.take() {
+        let _ = current.shutdown(Shutdown::Both);
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_slip_frames() {
+    let bytes = [0x2f, SLIP_END, 0x61, SLIP_ESC, 0x00];
+    let framed = frame(Framing::Slip, &bytes);
+    assert_eq!(
+      framed,
+      vec![
+        SLIP_END,
+        0x2f,
+        SLIP_ESC,
+        SLIP_ESC_END,
+        0x61,
+        SLIP_ESC,
+        SLIP_ESC_ESC,
+        0x00,
+        SLIP_END
+      ]
+    );
+
+    // Two frames split at every possible point, with an empty frame in between.
+    let mut stream = framed.clone();
+    stream.extend_from_slice(&[SLIP_END]);
+    stream.extend(frame(Framing::Slip, b"/b"));
+    for split in 0..stream.len() {
+      let mut deframer = Deframer::new(Framing::Slip);
+      deframer.push(&stream[..split]).unwrap();
+      deframer.push(&stream[split..]).unwrap();
+      assert_eq!(deframer.next_frame(), Some(bytes.to_vec()));
+      assert_eq!(deframer.next_frame(), Some(b"/b".to_vec()));
+      assert_eq!(deframer.next_frame(), None);
+      assert!(deframer.finish().is_ok());
+    }
+
+    let mut deframer = Deframer::new(Framing::Slip);
+    deframer.push(b"/a\0\0").unwrap();
+    assert!(deframer.finish().is_err());
+  }
+
+  #[test]
+  fn test_length_prefixed_frames() {
+    let framed = frame(Framing::LengthPrefix, b"/a\0\0");
+    assert_eq!(framed, b"\0\0\0\x04/a\0\0".to_vec());
+
+    let mut stream = framed.clone();
+    stream.extend_from_slice(&[0, 0, 0, 0]);
+    stream.extend(frame(Framing::LengthPrefix, b"/b\0\0,\0\0\0"));
+    for split in 0..stream.len() {
+      let mut deframer = Deframer::new(Framing::LengthPrefix);
+      deframer.push(&stream[..split]).unwrap();
+      deframer.push(&stream[split..]).unwrap();
+      assert_eq!(deframer.next_frame(), Some(b"/a\0\0".to_vec()));
+      assert_eq!(deframer.next_frame(), Some(b"/b\0\0,\0\0\0".to_vec()));
+      assert_eq!(deframer.next_frame(), None);
+      assert!(deframer.finish().is_ok());
+    }
+
+    let mut deframer = Deframer::new(Framing::LengthPrefix);
+    assert_eq!(
+      deframer.push(&[0x7f, 0, 0, 0]).unwrap_err().kind(),
+      ErrorKind::InvalidData
+    );
+  }
+
+  #[test]
+  fn test_detected_framing() {
+    let packet = b"/a\0\0,i\0\0\0\0\0\x01";
+    let mut unterminated = packet.to_vec();
+    unterminated.push(SLIP_END);
+    let streams = [
+      (frame(Framing::Slip, packet), Some(Framing::Slip)),
+      (unterminated, Some(Framing::Slip)),
+      (
+        frame(Framing::LengthPrefix, packet),
+        Some(Framing::LengthPrefix),
+      ),
+    ];
+    for (stream, expected) in streams.iter() {
+      // The first bytes may arrive one by one.
+      let mut deframer = Deframer::new(Framing::Auto);
+      for byte in stream.chunks(1) {
+        deframer.push(byte).unwrap();
+      }
+      assert_eq!(deframer.framing(), *expected);
+      assert_eq!(deframer.next_frame(), Some(packet.to_vec()));
+    }
+
+    let mut deframer = Deframer::new(Framing::Auto);
+    deframer.push(b"GET / HTTP/1.1").unwrap();
+    assert_eq!(deframer.framing(), None);
+    assert_eq!(deframer.next_frame(), Some(b"GET / HTTP/1.1".to_vec()));
+    deframer.push(b"\r\n").unwrap();
+    assert_eq!(deframer.next_frame(), Some(b"\r\n".to_vec()));
+
+    let mut deframer = Deframer::new(Framing::Auto);
+    deframer.push(&[0, 0]).unwrap();
+    assert_eq!(deframer.framing(), Some(Framing::Auto));
+    assert!(deframer.finish().is_ok());
+    assert_eq!(deframer.next_frame(), Some(vec![0, 0]));
+  }
+
+  #[test]
+  fn test_reconnects() {
+    for framing in [Framing::Slip, Framing::LengthPrefix].iter() {
+      let listener = Listener::bind("127.0.0.1:0", *framing).unwrap();
+      let sender = Sender::connect(listener.local_addr(), *framing).unwrap();
+      assert!(matches!(listener.recv(), Ok(Event::Connected(_))));
+
+      sender.send_bytes(b"/a\0\0,\0\0\0").unwrap();
+      match listener.recv() {
+        Ok(Event::Frame(bytes, _)) => assert_eq!(bytes, b"/a\0\0,\0\0\0".to_vec()),
+        other => panic!("{:?}", other),
+      }
+
+      // The next send opens a new connection once the previous one is gone.
+      sender.disconnect(None).unwrap();
+      assert!(matches!(listener.recv(), Ok(Event::Closed(_, None))));
+      sender.send_bytes(b"/b\0\0,\0\0\0").unwrap();
+      assert!(matches!(listener.recv(), Ok(Event::Connected(_))));
+      match listener.recv() {
+        Ok(Event::Frame(bytes, _)) => assert_eq!(bytes, b"/b\0\0,\0\0\0".to_vec()),
+        other => panic!("{:?}", other),
+      }
+    }
+  }
+}
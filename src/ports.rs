@@ -0,0 +1,143 @@
+//! Describes the ports a monitor listens on, and the options that only apply to some of them.
+
+use colored::Color;
+use std::fmt;
+use std::str::FromStr;
+
+/// A port to listen on, eg. `8000`, or `9000/tcp` to pick its protocol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Listen {
+  pub port: u16,
+  /// The protocol given after the port, when there is one.
+  pub proto: Option<Proto>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Proto {
+  Udp,
+  Tcp,
+}
+
+/// A value that applies to a single port when written `<port>=<value>`, eg. `8000=/light/*`, or
+/// to every port otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scoped<T> {
+  pub port: Option<u16>,
+  pub value: T,
+}
+
+impl Listen {
+  pub fn parse(listen: &str) -> Result<Listen, String> {
+    let (port, proto) = match listen.split_once('/') {
+      Some((port, "udp")) => (port, Some(Proto::Udp)),
+      Some((port, "tcp")) => (port, Some(Proto::Tcp)),
+      Some(_) => return Err(format!("`{}` protocol should be udp or tcp", listen)),
+      None => (listen, None),
+    };
+    let port = port
+      .parse::<u16>()
+      .map_err(|_| format!("`{}` is not a port, eg. 8000 or 9000/tcp", listen))?;
+    Ok(Listen { port, proto })
+  }
+}
+
+impl fmt::Display for Proto {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Proto::Udp => write!(f, "udp"),
+      Proto::Tcp => write!(f, "tcp"),
+    }
+  }
+}
+
+impl<T> Scoped<T> {
+  /// Parses `<port>=<value>` or `<value>`, `value` with `parse`.
+  pub fn parse<F>(scoped: &str, parse: F) -> Result<Scoped<T>, String>
+  where
+    F: Fn(&str) -> Result<T, String>,
+  {
+    if let Some((port, value)) = scoped.split_once('=') {
+      if let Ok(port) = port.parse::<u16>() {
+        return parse(value).map(|value| Scoped {
+          port: Some(port),
+          value,
+        });
+      }
+    }
+    parse(scoped).map(|value| Scoped { port: None, value })
+  }
+
+  /// Returns whether the value applies to `port`.
+  pub fn applies_to(&self, port: u16) -> bool {
+    self.port.iter().all(|scope| *scope == port)
+  }
+}
+
+/// Makes a `clap` value parser of scoped values out of the parser of the values themselves.
+pub fn scoped<T: 'static>(
+  parse: fn(&str) -> Result<T, String>,
+) -> impl Fn(&str) -> Result<Scoped<T>, String> + Clone + Send + Sync + 'static {
+  move |scoped| Scoped::parse(scoped, parse)
+}
+
+/// Parses a colour name, eg. `yellow` or `bright blue`.
+pub fn parse_color(color: &str) -> Result<Color, String> {
+  Color::from_str(color).map_err(|_| {
+    format!(
+      "`{}` is not a colour, eg. red, green, yellow, blue, magenta, cyan or bright blue",
+      color
+    )
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_listen() {
+    let ports = [
+      ("8000", 8000, None),
+      ("57120/udp", 57120, Some(Proto::Udp)),
+      ("9000/tcp", 9000, Some(Proto::Tcp)),
+    ];
+
+    for (listen, port, proto) in ports.iter() {
+      assert_eq!(
+        Listen::parse(listen),
+        Ok(Listen {
+          port: *port,
+          proto: *proto
+        }),
+        "{}",
+        listen
+      );
+    }
+    for listen in ["70000", "9000/sctp", "tcp", ""].iter() {
+      assert!(Listen::parse(listen).is_err(), "{}", listen);
+    }
+  }
+
+  #[test]
+  fn test_scoped() {
+    let parse = |value: &str| Ok(value.to_string());
+    let values = [
+      ("8000=/light/*", Some(8000), "/light/*"),
+      ("/light/*", None, "/light/*"),
+      ("57120=arg[0] == 1", Some(57120), "arg[0] == 1"),
+      ("arg[0] == 1", None, "arg[0] == 1"),
+      ("yellow", None, "yellow"),
+    ];
+
+    for (scoped, port, value) in values.iter() {
+      let parsed = Scoped::parse(scoped, parse).unwrap();
+      assert_eq!(parsed.port, *port, "{}", scoped);
+      assert_eq!(parsed.value, *value, "{}", scoped);
+    }
+
+    let scoped = Scoped::parse("8000=/a", parse).unwrap();
+    assert!(scoped.applies_to(8000));
+    assert!(!scoped.applies_to(9000));
+    assert!(Scoped::parse("/a", parse).unwrap().applies_to(9000));
+  }
+}
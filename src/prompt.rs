@@ -1,8 +1,9 @@
-use super::task::{monitor, send, Task};
+use super::osc::{SendOptions, Transport};
+use super::task::{monitor, send, Failure, MonitorOptions, Port, Target, Task};
 use crate::{DEFAULT_IP, DEFAULT_PORT};
 use inquire::{error::InquireResult, CustomType, Select, Text};
 
-pub fn prompt() -> InquireResult<()> {
+pub fn prompt() -> InquireResult<Result<(), Failure>> {
   let tasks = vec![
     Task::Monitor("monitor OSC messages".to_string()),
     Task::Send("send OSC messages".to_string()),
@@ -17,16 +18,29 @@ pub fn prompt() -> InquireResult<()> {
     .prompt()
     .unwrap();
 
-  match task {
-    Task::Monitor(_) => monitor(port),
+  let result = match task {
+    Task::Monitor(_) => monitor(MonitorOptions {
+      ports: vec![Port {
+        port,
+        ..Port::default()
+      }],
+      ..MonitorOptions::default()
+    }),
     Task::Send(_) => {
-      let address: String = Text::new("What host IP do you want to connect to?")
-        .with_default(DEFAULT_IP)
-        .prompt()
-        .unwrap();
-      send(port, address)
+      let address: String =
+        Text::new("What host (IP address or hostname) do you want to connect to?")
+          .with_default(DEFAULT_IP)
+          .prompt()
+          .unwrap();
+      let target = Target {
+        host: address,
+        port,
+        transport: Transport::Udp,
+        options: SendOptions::default(),
+      };
+      send(target, false)
     }
   };
 
-  Ok(())
+  Ok(result)
 }
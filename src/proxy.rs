@@ -0,0 +1,242 @@
+//! Decides where the proxy forwards packets, and what happens to them on the way to simulate a
+//! bad network.
+
+use super::osc::address::Pattern;
+use super::osc::Packet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Forwards the packets holding a message whose address matches `pattern` to `to` instead of
+/// the default destinations, eg. `/light/*=10.0.0.6:8000`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Route {
+  pattern: Pattern,
+  /// A `host:port` destination, eg. `10.0.0.6:8000`, `[::1]:9000` or `studio.local:57110`.
+  pub to: String,
+}
+
+impl Route {
+  /// Parses a route written as `PATTERN=HOST:PORT`.
+  pub fn parse(route: &str) -> Result<Route, String> {
+    let (pattern, to) = match route.split_once('=') {
+      Some(split) => split,
+      None => return Err(format!("`{}` should be written PATTERN=HOST:PORT", route)),
+    };
+    Ok(Route {
+      pattern: Pattern::parse(pattern)?,
+      to: parse_destination(to)?,
+    })
+  }
+
+  /// Returns whether any message in `packet` matches the route's pattern.
+  pub fn matches(&self, packet: &Packet) -> bool {
+    packet
+      .clone()
+      .into_msgs()
+      .iter()
+      .any(|msg| self.pattern.matches(&msg.addr))
+  }
+}
+
+/// Checks that a destination is written `HOST:PORT`, eg. `10.0.0.5:57110`, `[fe80::1]:9000` or
+/// `studio.local:57110`, without resolving it.
+pub fn parse_destination(to: &str) -> Result<String, String> {
+  match to.rsplit_once(':') {
+    Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(to.to_string()),
+    _ => Err(format!(
+      "`{}` should be written HOST:PORT, eg. 10.0.0.5:57110",
+      to
+    )),
+  }
+}
+
+/// The destinations of `packet`: those of every route it matches, or `default` when it matches
+/// none. Packets that could not be decoded always go to `default`.
+pub fn destinations<'a>(
+  packet: Option<&Packet>,
+  routes: &'a [Route],
+  default: &'a [String],
+) -> Vec<&'a String> {
+  let mut routed = Vec::new();
+  if let Some(packet) = packet {
+    for route in routes.iter().filter(|route| route.matches(packet)) {
+      if !routed.contains(&&route.to) {
+        routed.push(&route.to);
+      }
+    }
+  }
+  if routed.is_empty() {
+    default.iter().collect()
+  } else {
+    routed
+  }
+}
+
+/// What happens to packets on their way through the proxy, in both directions.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Faults {
+  /// Probability for a packet to be dropped, from 0 to 1.
+  pub drop: f64,
+  /// Probability for a packet to be sent twice, from 0 to 1.
+  pub duplicate: f64,
+  /// How long packets are held before being sent on.
+  pub delay: Duration,
+}
+
+impl Faults {
+  /// Decides how many copies of a packet are sent on: 0 when it is dropped, 2 when it is
+  /// duplicated, 1 otherwise.
+  pub fn copies(&self, random: &mut Random) -> usize {
+    if random.chance(self.drop) {
+      0
+    } else if random.chance(self.duplicate) {
+      2
+    } else {
+      1
+    }
+  }
+}
+
+/// Parses a probability written as a percentage, eg. `10%`, or as a fraction, eg. `0.1`.
+pub fn parse_probability(probability: &str) -> Result<f64, String> {
+  let value = match probability.strip_suffix('%') {
+    Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+    None => probability.trim().parse::<f64>(),
+  };
+  match value {
+    Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+    _ => Err(format!(
+      "`{}` should be a percentage from 0% to 100%, eg. 10%",
+      probability
+    )),
+  }
+}
+
+/// A xorshift pseudo-random generator, plenty for simulating packet loss.
+pub struct Random {
+  state: u64,
+}
+
+impl Random {
+  pub fn new(seed: u64) -> Self {
+    // The state must never be zero.
+    Random { state: seed | 1 }
+  }
+
+  /// Seeded from the current time.
+  pub fn from_time() -> Self {
+    let nanos = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_nanos() as u64)
+      .unwrap_or(0);
+    Random::new(nanos)
+  }
+
+  /// A number in `[0, 1)`.
+  pub fn next_f64(&mut self) -> f64 {
+    self.state ^= self.state << 13;
+    self.state ^= self.state >> 7;
+    self.state ^= self.state << 17;
+    (self.state >> 11) as f64 / (1u64 << 53) as f64
+  }
+
+  /// Returns `true` with the given probability.
+  pub fn chance(&mut self, probability: f64) -> bool {
+    probability > 0.0 && self.next_f64() < probability
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::osc::{msg, Bundle, Type};
+  use super::super::time;
+  use super::*;
+
+  #[test]
+  fn test_routes() {
+    let routes = vec![
+      Route::parse("/light/*=10.0.0.6:8000").unwrap(),
+      Route::parse("/video/*=[::1]:9000").unwrap(),
+      Route::parse("//level=10.0.0.6:8000").unwrap(),
+    ];
+    let default = vec!["10.0.0.5:57110".to_string()];
+    let to = |packet: Packet| {
+      destinations(Some(&packet), &routes, &default)
+        .into_iter()
+        .cloned()
+        .collect::<Vec<String>>()
+    };
+
+    assert_eq!(to(msg("/light/1", vec![]).into()), vec!["10.0.0.6:8000"]);
+    assert_eq!(
+      to(msg("/light/level", vec![Type::Int(1)]).into()),
+      vec!["10.0.0.6:8000"]
+    );
+    assert_eq!(to(msg("/audio/1", vec![]).into()), vec!["10.0.0.5:57110"]);
+    let bundle = Bundle {
+      timetag: time::ntp_now(),
+      content: vec![
+        Packet::from(msg("/video/1", vec![])).into(),
+        Packet::from(msg("/light/2", vec![])).into(),
+      ],
+    };
+    assert_eq!(
+      to(Packet::Bundle(bundle)),
+      vec!["10.0.0.6:8000", "[::1]:9000"]
+    );
+    assert_eq!(
+      destinations(None, &routes, &default),
+      vec![&"10.0.0.5:57110".to_string()]
+    );
+  }
+
+  #[test]
+  fn test_invalid_routes() {
+    for route in [
+      "/light/*",
+      "/light/*=10.0.0.6",
+      "/light/*=:8000",
+      "/light/*=10.0.0.6:80000",
+      "light=10.0.0.6:8000",
+    ]
+    .iter()
+    {
+      assert!(Route::parse(route).is_err(), "{}", route);
+    }
+  }
+
+  #[test]
+  fn test_probabilities() {
+    assert_eq!(parse_probability("10%"), Ok(0.1));
+    assert_eq!(parse_probability("0.25"), Ok(0.25));
+    assert_eq!(parse_probability("100%"), Ok(1.0));
+    for probability in ["150%", "-1", "often"].iter() {
+      assert!(parse_probability(probability).is_err(), "{}", probability);
+    }
+  }
+
+  #[test]
+  fn test_faults() {
+    let mut random = Random::new(42);
+    let never = Faults::default();
+    assert!((0..1000).all(|_| never.copies(&mut random) == 1));
+    let always_dropped = Faults {
+      drop: 1.0,
+      ..Faults::default()
+    };
+    assert!((0..1000).all(|_| always_dropped.copies(&mut random) == 0));
+
+    let lossy = Faults {
+      drop: 0.1,
+      duplicate: 0.2,
+      ..Faults::default()
+    };
+    let copies = (0..10_000)
+      .map(|_| lossy.copies(&mut random))
+      .collect::<Vec<usize>>();
+    let dropped = copies.iter().filter(|c| **c == 0).count();
+    let duplicated = copies.iter().filter(|c| **c == 2).count();
+    assert!((800..1200).contains(&dropped), "{}", dropped);
+    // Only packets that were not dropped can be duplicated.
+    assert!((1600..2000).contains(&duplicated), "{}", duplicated);
+  }
+}
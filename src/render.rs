@@ -0,0 +1,467 @@
+//! Renders received OSC packets back into `oscd`'s own literal syntax, so that what the monitor
+//! shows can be typed as-is into the send mode, eg. `Int(850)` is rendered as `850`.
+//!
+//! The send mode has no syntax for a few values, which are shown the way Rust debug-prints them
+//! instead: NaN and infinite floats (eg. `NaN`, `-inf`), strings holding a double quote or a
+//! control character (eg. `"say \"hi\""`), and chars other than letters (eg. `'1'`).
+//!
+//! Timetags are rendered as ISO-8601 UTC literals, eg. `@2026-10-17T20:00:00.5Z`, which the send
+//! mode accepts as well. In the monitor they are followed by how far they are from the local clock
+//! at the time of reception, eg. `(in +120 ms)` or `(3.0 s late)`.
+
+use super::osc::rosc::{OscPacket, OscTime};
+use super::osc::{Bundle, Message, Packet, Type};
+use super::table::{TableReceived, TableSuccess};
+use super::time;
+use std::fmt;
+use std::time::SystemTime;
+
+/// Renders a single OSC argument as an `oscd` literal, see above for the values that cannot be
+/// typed back.
+pub fn literal(arg: &Type) -> String {
+  match arg {
+    Type::Int(val) => format!("{}", val),
+    Type::Long(val) => format!("{}_i64", val),
+    Type::Float(val) => decimal(*val, val.is_finite()),
+    Type::Double(val) => format!("{}_f64", decimal(*val, val.is_finite())),
+    Type::String(val) => string_literal(val),
+    Type::Char(val) => format!("'{}'", val),
+    Type::Bool(val) => format!("{}", val),
+    Type::Blob(val) => format!(
+      "%[{}]",
+      val
+        .iter()
+        .map(|b| b.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+    ),
+    // The "immediately" timetag is not meant as a date, it is shown raw.
+    Type::Time(val) if val.seconds == 0 => format!(
+      "@{}.{}",
+      val.seconds,
+      time::decimal_from_fraction(val.fractional)
+    ),
+    Type::Time(val) => format!("@{}", time::iso8601(*val)),
+    Type::Color(val) => format!(
+      "#{:02X}{:02X}{:02X}{:02X}",
+      val.red, val.green, val.blue, val.alpha
+    ),
+    Type::Midi(val) => format!(
+      "~{:02X}{:02X}{:02X}{:02X}",
+      val.port, val.status, val.data1, val.data2
+    ),
+    Type::Array(val) => format!(
+      "[{}]",
+      val
+        .content
+        .iter()
+        .map(literal)
+        .collect::<Vec<String>>()
+        .join(",")
+    ),
+    Type::Nil => "Nil".to_string(),
+    Type::Inf => "Inf".to_string(),
+  }
+}
+
+// Finite floats are written out in full with a decimal point, eg. `0.00001` or `1.0`, the send
+// mode reads neither exponents nor integers as floats.
+fn decimal<F: fmt::Display + fmt::Debug>(val: F, finite: bool) -> String {
+  let decimal = match finite {
+    true => val.to_string(),
+    false => return format!("{:?}", val),
+  };
+  match decimal.contains('.') {
+    true => decimal,
+    false => format!("{}.0", decimal),
+  }
+}
+
+// Strings are read as-is up to the next double quote by the send mode, without escapes.
+fn string_literal(val: &str) -> String {
+  match val.contains(|c: char| c == '"' || c.is_control()) {
+    true => format!("{:?}", val),
+    false => format!("\"{}\"", val),
+  }
+}
+
+/// Renders a list of OSC arguments as space separated `oscd` literals.
+pub fn literals(args: &[Type]) -> String {
+  args.iter().map(literal).collect::<Vec<String>>().join(" ")
+}
+
+/// Describes where a timetag stands relative to `at`, eg. `in +120 ms`, `3.0 s late` or
+/// `immediately`.
+pub fn relative(timetag: OscTime, at: SystemTime) -> String {
+  if time::is_immediate(timetag) {
+    return "immediately".to_string();
+  }
+  let lead = time::lead(timetag, at);
+  let amount = if lead.abs() < 1.0 {
+    format!("{:.0} ms", lead.abs() * 1_000.0)
+  } else {
+    format!("{:.1} s", lead.abs())
+  };
+  if lead >= 0.0 {
+    format!("in +{}", amount)
+  } else {
+    format!("{} late", amount)
+  }
+}
+
+// Like `literals`, with every timetag argument followed by where it stands relative to `at`.
+fn annotated_literals(args: &[Type], at: SystemTime) -> String {
+  args
+    .iter()
+    .map(|arg| match arg {
+      Type::Time(timetag) => format!("{} ({})", literal(arg), relative(*timetag, at)),
+      _ => literal(arg),
+    })
+    .collect::<Vec<String>>()
+    .join(" ")
+}
+
+/// Builds the OSC type tag string of the given arguments, eg. `,isf[ii]`.
+pub fn type_tags(args: &[Type]) -> String {
+  let mut tags = ",".to_string();
+  for arg in args {
+    push_type_tag(arg, &mut tags);
+  }
+  tags
+}
+
+fn push_type_tag(arg: &Type, tags: &mut String) {
+  match arg {
+    Type::Int(_) => tags.push('i'),
+    Type::Long(_) => tags.push('h'),
+    Type::Float(_) => tags.push('f'),
+    Type::Double(_) => tags.push('d'),
+    Type::String(_) => tags.push('s'),
+    Type::Char(_) => tags.push('c'),
+    Type::Bool(true) => tags.push('T'),
+    Type::Bool(false) => tags.push('F'),
+    Type::Blob(_) => tags.push('b'),
+    Type::Time(_) => tags.push('t'),
+    Type::Color(_) => tags.push('r'),
+    Type::Midi(_) => tags.push('m'),
+    Type::Nil => tags.push('N'),
+    Type::Inf => tags.push('I'),
+    Type::Array(val) => {
+      tags.push('[');
+      for arg in &val.content {
+        push_type_tag(arg, tags);
+      }
+      tags.push(']');
+    }
+  }
+}
+
+/// Renders a packet received at `received` as table rows.
+///
+/// A message takes a single row, a bundle takes one row for itself (showing its timetag) followed
+/// by the rows of its content, indented by their nesting depth. Only the first row carries the
+/// `source` and `packet_size`.
+pub fn rows(
+  packet: &Packet,
+  source: String,
+  packet_size: String,
+  received: SystemTime,
+) -> Vec<TableReceived> {
+  let head = (source, packet_size);
+  let mut rows = vec![];
+  match packet {
+    Packet::Message(msg) => push_message_row(msg, 0, &head, received, &mut rows),
+    Packet::Bundle(bundle) => push_bundle_rows(bundle, 0, &head, received, &mut rows),
+  }
+  rows
+}
+
+fn row_head(head: &(String, String), rows: &[TableReceived]) -> (String, String) {
+  if rows.is_empty() {
+    head.clone()
+  } else {
+    (String::new(), String::new())
+  }
+}
+
+fn push_message_row(
+  msg: &Message,
+  depth: usize,
+  head: &(String, String),
+  received: SystemTime,
+  rows: &mut Vec<TableReceived>,
+) {
+  let (source, packet_size) = row_head(head, rows);
+  rows.push(TableReceived::new(
+    source,
+    packet_size,
+    format!("{}{}", indent(depth), msg.addr),
+    type_tags(&msg.args),
+    annotated_literals(&msg.args, received),
+  ));
+}
+
+fn push_bundle_rows(
+  bundle: &Bundle,
+  depth: usize,
+  head: &(String, String),
+  received: SystemTime,
+  rows: &mut Vec<TableReceived>,
+) {
+  let (source, packet_size) = row_head(head, rows);
+  rows.push(TableReceived::new(
+    source,
+    packet_size,
+    format!("{}#bundle", indent(depth)),
+    String::new(),
+    annotated_literals(&[Type::Time(bundle.timetag)], received),
+  ));
+  for packet in &bundle.content {
+    match packet {
+      OscPacket::Message(msg) => push_message_row(msg, depth + 1, head, received, rows),
+      OscPacket::Bundle(bundle) => push_bundle_rows(bundle, depth + 1, head, received, rows),
+    }
+  }
+}
+
+/// Collects the timetags of `packet`'s bundles, nested ones included, leaving out the
+/// "immediately" ones.
+pub fn bundle_timetags(packet: &Packet) -> Vec<OscTime> {
+  let mut timetags = vec![];
+  if let Packet::Bundle(bundle) = packet {
+    push_bundle_timetags(bundle, &mut timetags);
+  }
+  timetags
+}
+
+fn push_bundle_timetags(bundle: &Bundle, timetags: &mut Vec<OscTime>) {
+  if !time::is_immediate(bundle.timetag) {
+    timetags.push(bundle.timetag);
+  }
+  for packet in &bundle.content {
+    if let OscPacket::Bundle(bundle) = packet {
+      push_bundle_timetags(bundle, timetags);
+    }
+  }
+}
+
+/// Renders a sent packet as table rows, laid out like `rows`.
+pub fn sent_rows(packet: &Packet, packet_size: String) -> Vec<TableSuccess> {
+  let mut rows = vec![];
+  push_sent_rows(&packet.clone().into(), 0, &packet_size, &mut rows);
+  rows
+}
+
+fn push_sent_rows(
+  packet: &OscPacket,
+  depth: usize,
+  packet_size: &str,
+  rows: &mut Vec<TableSuccess>,
+) {
+  let packet_size = if rows.is_empty() {
+    packet_size.to_string()
+  } else {
+    String::new()
+  };
+  match packet {
+    OscPacket::Message(msg) => rows.push(TableSuccess::new(
+      packet_size,
+      format!("{}{}", indent(depth), msg.addr),
+      format!("{:?}", msg.args),
+    )),
+    OscPacket::Bundle(bundle) => {
+      rows.push(TableSuccess::new(
+        packet_size,
+        format!("{}#bundle", indent(depth)),
+        literal(&Type::Time(bundle.timetag)),
+      ));
+      for packet in &bundle.content {
+        push_sent_rows(packet, depth + 1, "", rows);
+      }
+    }
+  }
+}
+
+fn indent(depth: usize) -> String {
+  "  ".repeat(depth)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::osc::rosc::{OscArray, OscColor, OscMidiMessage};
+  use super::super::task;
+  use super::*;
+  use std::time::{Duration, UNIX_EPOCH};
+  use tabled::Tabled;
+
+  #[test]
+  fn test_literals() {
+    let args = vec![
+      Type::String("default".to_string()),
+      Type::Int(-1),
+      Type::Long(12),
+      Type::Float(850.0),
+      Type::Double(1.25),
+      Type::Char('A'),
+      Type::Bool(true),
+      Type::Blob(vec![10, 20, 30]),
+      Type::Time(OscTime {
+        seconds: 2_208_988_800,
+        fractional: 1 << 30,
+      }),
+      Type::Time(OscTime {
+        seconds: 0,
+        fractional: 1,
+      }),
+      Type::Color(OscColor {
+        red: 0x2F,
+        green: 0x14,
+        blue: 0xDF,
+        alpha: 0x2A,
+      }),
+      Type::Midi(OscMidiMessage {
+        port: 0x01,
+        status: 0xF1,
+        data1: 0x4F,
+        data2: 0xA4,
+      }),
+      Type::Array(OscArray::from_iter(vec![Type::Int(12), Type::Bool(false)])),
+      Type::Nil,
+      Type::Inf,
+    ];
+
+    assert_eq!(
+      literals(&args),
+      r#""default" -1 12_i64 850.0 1.25_f64 'A' true %[10,20,30] @1970-01-01T00:00:00.25Z @0.0000000002 #2F14DF2A ~01F14FA4 [12,false] Nil Inf"#
+    );
+    assert_eq!(type_tags(&args), ",sihfdcTbttrm[iF]NI");
+  }
+
+  #[test]
+  fn test_round_trips() {
+    let args = vec![
+      Type::String("C:\\oscd é".to_string()),
+      Type::Int(-1),
+      Type::Long(12),
+      Type::Float(0.1),
+      Type::Float(1e-5),
+      Type::Float(-1e16),
+      Type::Float(f32::MAX),
+      Type::Double(-1.25),
+      Type::Double(1e-5),
+      Type::Double(1e16),
+      Type::Char('é'),
+      Type::Bool(false),
+      Type::Blob(vec![0, 255]),
+      Type::Time(OscTime {
+        seconds: 3_000_000_000,
+        fractional: 123_456_789,
+      }),
+      Type::Color(OscColor {
+        red: 0x2F,
+        green: 0x14,
+        blue: 0xDF,
+        alpha: 0x2A,
+      }),
+      Type::Midi(OscMidiMessage {
+        port: 0x01,
+        status: 0xF1,
+        data1: 0x4F,
+        data2: 0xA4,
+      }),
+      Type::Array(OscArray::from_iter(vec![Type::Int(12), Type::Nil])),
+      Type::Inf,
+    ];
+    let input = format!("/a {}", literals(&args));
+    match task::parse_input(&input) {
+      Ok(Packet::Message(msg)) => assert_eq!(msg.args, args, "{}", input),
+      _ => panic!("{} was not read back", input),
+    }
+
+    assert_eq!(
+      literals(&[Type::Float(1e-5), Type::Double(1e16)]),
+      "0.00001 10000000000000000.0_f64"
+    );
+
+    let unwritable = [
+      Type::Float(f32::NAN),
+      Type::Double(f64::NEG_INFINITY),
+      Type::String("say \"hi\"".to_string()),
+      Type::String("a\nb".to_string()),
+      Type::Char('1'),
+    ];
+    assert_eq!(
+      literals(&unwritable),
+      r#"NaN -inf_f64 "say \"hi\"" "a\nb" '1'"#
+    );
+  }
+
+  #[test]
+  fn test_bundle_rows() {
+    let received = UNIX_EPOCH + Duration::from_secs(1_792_267_200);
+    let timetag = time::ntp_from_system(received + Duration::from_millis(120));
+    let inner = Bundle {
+      timetag,
+      content: vec![OscPacket::Message(Message {
+        addr: "/b".to_string(),
+        args: vec![Type::String("x".to_string())],
+      })],
+    };
+    let packet = Packet::Bundle(Bundle {
+      timetag,
+      content: vec![
+        OscPacket::Message(Message {
+          addr: "/a".to_string(),
+          args: vec![Type::Int(1), Type::Int(2)],
+        }),
+        OscPacket::Bundle(inner),
+      ],
+    });
+
+    let fields = rows(
+      &packet,
+      "127.0.0.1:9000".to_string(),
+      "64 bytes".to_string(),
+      received,
+    )
+    .iter()
+    .map(|row| row.fields().iter().map(|f| f.to_string()).collect())
+    .collect::<Vec<Vec<String>>>();
+    let addresses = fields
+      .iter()
+      .map(|row| row[2].as_str())
+      .collect::<Vec<&str>>();
+    assert_eq!(addresses, ["#bundle", "  /a", "  #bundle", "    /b"]);
+    assert_eq!(fields[0][0], "127.0.0.1:9000");
+    assert_eq!(fields[1][0], "");
+    assert_eq!(fields[1][3], ",ii");
+    assert_eq!(fields[1][4], "1 2");
+    assert_eq!(fields[0][4], "@2026-10-17T20:00:00.12Z (in +120 ms)");
+    assert_eq!(bundle_timetags(&packet), vec![timetag, timetag]);
+  }
+
+  #[test]
+  fn test_relative_timetags() {
+    let at = UNIX_EPOCH + Duration::from_secs(1_792_267_200);
+    let timetag = |ms: i64| {
+      time::ntp_from_system(if ms < 0 {
+        at - Duration::from_millis(ms.unsigned_abs())
+      } else {
+        at + Duration::from_millis(ms as u64)
+      })
+    };
+    assert_eq!(relative(timetag(120), at), "in +120 ms");
+    assert_eq!(relative(timetag(-3_000), at), "3.0 s late");
+    assert_eq!(relative(timetag(0), at), "in +0 ms");
+    assert_eq!(
+      relative(
+        OscTime {
+          seconds: 0,
+          fractional: 1
+        },
+        at
+      ),
+      "immediately"
+    );
+  }
+}
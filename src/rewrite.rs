@@ -0,0 +1,115 @@
+//! Rewrites the address of OSC messages, eg. to replay traffic recorded from one device to a
+//! receiver expecting another address space.
+
+use super::osc::address::Pattern;
+use super::osc::rosc::OscPacket;
+use super::osc::{Message, Packet};
+
+/// Replaces the leading address parts matched by `from` with `to`.
+///
+/// `/synth/*=/visual` rewrites `/synth/1/freq` to `/visual/freq`, a pattern matching the whole
+/// address replaces it entirely. The longest matching prefix wins.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rewrite {
+  from: Pattern,
+  to: String,
+}
+
+impl Rewrite {
+  /// Parses a rewrite written as `FROM=TO`, eg. `/synth/*=/visual`.
+  pub fn parse(rewrite: &str) -> Result<Rewrite, String> {
+    let (from, to) = match rewrite.split_once('=') {
+      Some(split) => split,
+      None => return Err(format!("`{}` should be written FROM=TO", rewrite)),
+    };
+    if !to.starts_with('/') {
+      return Err(format!("`{}` should start with / eg. /s_new", to));
+    }
+    Ok(Rewrite {
+      from: Pattern::parse(from)?,
+      to: to.trim_end_matches('/').to_string(),
+    })
+  }
+
+  /// Returns the rewritten address, or `None` if no prefix of `addr` matches.
+  pub fn apply(&self, addr: &str) -> Option<String> {
+    let parts = addr.split('/').skip(1).collect::<Vec<&str>>();
+    (1..=parts.len()).rev().find_map(|len| {
+      let prefix = format!("/{}", parts[..len].join("/"));
+      if !self.from.matches(&prefix) {
+        return None;
+      }
+      let rest = &parts[len..];
+      Some(if rest.is_empty() && self.to.is_empty() {
+        "/".to_string()
+      } else if rest.is_empty() {
+        self.to.clone()
+      } else {
+        format!("{}/{}", self.to, rest.join("/"))
+      })
+    })
+  }
+}
+
+/// Rewrites the address of every message in `packet` with the first of `rewrites` that applies.
+pub fn rewrite_packet(packet: Packet, rewrites: &[Rewrite]) -> Packet {
+  match packet {
+    Packet::Message(msg) => Packet::Message(rewrite_message(msg, rewrites)),
+    Packet::Bundle(mut bundle) => {
+      bundle.content = bundle
+        .content
+        .into_iter()
+        .map(|packet| rewrite_packet(packet.into(), rewrites).into())
+        .collect::<Vec<OscPacket>>();
+      Packet::Bundle(bundle)
+    }
+  }
+}
+
+fn rewrite_message(mut msg: Message, rewrites: &[Rewrite]) -> Message {
+  if let Some(addr) = rewrites.iter().find_map(|r| r.apply(&msg.addr)) {
+    msg.addr = addr;
+  }
+  msg
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_rewrites() {
+    let rewrites = [
+      ("/synth/*=/visual", "/synth/1/freq", Some("/visual/freq")),
+      ("/synth/*=/visual", "/synth/1", Some("/visual")),
+      (
+        "/synth=/visual/synth",
+        "/synth/1/freq",
+        Some("/visual/synth/1/freq"),
+      ),
+      ("/a/b=/c", "/a/b", Some("/c")),
+      ("//gain=/volume", "/mixer/gain", Some("/volume")),
+      ("/synth/*=/visual", "/drum/1", None),
+      ("/a=/", "/a/b", Some("/b")),
+      ("/a=/", "/a", Some("/")),
+    ];
+
+    for (rewrite, addr, expected) in rewrites.iter() {
+      let rewrite = Rewrite::parse(rewrite).unwrap();
+      assert_eq!(
+        rewrite.apply(addr).as_deref(),
+        *expected,
+        "{:?} on {}",
+        rewrite,
+        addr
+      );
+    }
+  }
+
+  #[test]
+  fn test_invalid_rewrites() {
+    for rewrite in ["/a", "/a=b", "a=/b"].iter() {
+      assert!(Rewrite::parse(rewrite).is_err(), "{}", rewrite);
+    }
+  }
+}
@@ -0,0 +1,531 @@
+//! Rule sets transforming the messages the proxy forwards, eg. to bridge devices that do not
+//! share the same address space.
+//!
+//! A rule file holds one rule per line, `#` starts a comment and blank lines are ignored:
+//!
+//! ```text
+//! # <pattern> -> <address> [<argument> ...]
+//! /fader/{n} -> /mixer/ch/{n}/gain $0(0..127=0..1)
+//! /knob/{n} -> /synth/param {n}:i $0:f
+//! /xy/* -> /pad $1 $0
+//! ```
+//!
+//! - `<pattern>` is matched part by part against the address of every message. A part written
+//!   `{name}` matches any single part and captures it, other parts are OSC address patterns, eg.
+//!   `fader`, `*` or `ch[1-4]`.
+//! - `<address>` is the new address, where `{name}` is replaced with what was captured.
+//! - each `<argument>` picks an argument of the new message, in order, either an argument of the
+//!   matched message (`$0` is the first one) or a capture (`{name}`, a string). Arguments that
+//!   are not picked are dropped. Without any `<argument>` the arguments are left untouched.
+//! - `$0(0..127=0..1)` rescales the argument linearly from the first range to the second one,
+//!   giving a float (or a double, for a double).
+//! - a trailing `:i`, `:h`, `:f`, `:d` or `:s` converts the argument to an int32, int64, float32,
+//!   float64 or string, eg. `$0:f` or `{n}:i`. Floats are rounded to the nearest integer, NaNs
+//!   and floats out of the integer's range cannot be converted.
+//!
+//! Every message is transformed by the first rule that matches its address, messages that no
+//! rule matches are left as they are.
+
+use super::osc::address::Pattern;
+use super::osc::rosc::OscPacket;
+use super::osc::{Message, Packet, Type};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The rules of a rule file, in the order they were written.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rules {
+  rules: Vec<Rule>,
+}
+
+/// A single rule, eg. `/fader/{n} -> /mixer/ch/{n}/gain $0(0..127=0..1)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+  /// The line the rule was written on, 1 for the first one.
+  pub line: usize,
+  parts: Vec<Part>,
+  addr: String,
+  args: Option<Vec<Arg>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+  Capture(String),
+  Pattern(Pattern),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Arg {
+  source: Source,
+  // `(from_min, from_max, to_min, to_max)`.
+  scale: Option<(f64, f64, f64, f64)>,
+  convert: Option<Kind>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Source {
+  Arg(usize),
+  Capture(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+  Int,
+  Long,
+  Float,
+  Double,
+  String,
+}
+
+impl Rules {
+  /// Reads the rule file at `path`.
+  ///
+  /// Errors describe the offending line, eg. `bridge.rules:3: ...`.
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Rules, String> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Rules::parse(&content).map_err(|e| format!("{}:{}", path.display(), e))
+  }
+
+  /// Parses the content of a rule file, errors start with the number of the offending line.
+  pub fn parse(content: &str) -> Result<Rules, String> {
+    let rules = content
+      .lines()
+      .enumerate()
+      .map(|(i, line)| (i + 1, line.split('#').next().unwrap_or_default().trim()))
+      .filter(|(_, line)| !line.is_empty())
+      .map(|(line, rule)| Rule::parse(rule, line).map_err(|e| format!("{}: {}", line, e)))
+      .collect::<Result<Vec<Rule>, String>>()?;
+    Ok(Rules { rules })
+  }
+
+  pub fn len(&self) -> usize {
+    self.rules.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.rules.is_empty()
+  }
+
+  /// Transforms every message of `packet` with the first rule matching its address.
+  ///
+  /// Messages a matching rule cannot be applied to, eg. because they lack the argument it picks,
+  /// are left untouched and described in `errors`.
+  pub fn apply(&self, packet: Packet, errors: &mut Vec<String>) -> Packet {
+    match packet {
+      Packet::Message(msg) => Packet::Message(self.apply_message(msg, errors)),
+      Packet::Bundle(mut bundle) => {
+        bundle.content = bundle
+          .content
+          .into_iter()
+          .map(|packet| self.apply(packet.into(), errors).into())
+          .collect::<Vec<OscPacket>>();
+        Packet::Bundle(bundle)
+      }
+    }
+  }
+
+  fn apply_message(&self, msg: Message, errors: &mut Vec<String>) -> Message {
+    for rule in self.rules.iter() {
+      if let Some(captures) = rule.captures(&msg.addr) {
+        return match rule.transform(&msg, &captures) {
+          Ok(transformed) => transformed,
+          Err(e) => {
+            errors.push(format!(
+              "rule on line {} does not apply to {}, {}",
+              rule.line, msg.addr, e
+            ));
+            msg
+          }
+        };
+      }
+    }
+    msg
+  }
+}
+
+impl Rule {
+  /// Parses a rule written as `<pattern> -> <address> [<argument> ...]`.
+  pub fn parse(rule: &str, line: usize) -> Result<Rule, String> {
+    let tokens = rule.split_whitespace().collect::<Vec<&str>>();
+    if tokens.len() < 3 || tokens[1] != "->" {
+      return Err(format!(
+        "`{}` should be written <pattern> -> <address> [<argument> ...]",
+        rule
+      ));
+    }
+    let parts = match tokens[0].strip_prefix('/') {
+      Some(rest) => rest
+        .split('/')
+        .map(|part| match capture(part) {
+          Some(name) => Ok(Part::Capture(name.to_string())),
+          None if part.is_empty() => Err(format!("`{}` has an empty part", tokens[0])),
+          None => Pattern::parse(&format!("/{}", part)).map(Part::Pattern),
+        })
+        .collect::<Result<Vec<Part>, String>>()?,
+      None => return Err(format!("`{}` should start with / eg. /fader", tokens[0])),
+    };
+    let captured = parts
+      .iter()
+      .filter_map(|part| match part {
+        Part::Capture(name) => Some(name.as_str()),
+        Part::Pattern(_) => None,
+      })
+      .collect::<Vec<&str>>();
+    let addr = tokens[2];
+    if !addr.starts_with('/') {
+      return Err(format!("`{}` should start with / eg. /mixer", addr));
+    }
+    for part in addr.split('/').skip(1) {
+      match capture(part) {
+        Some(name) if !captured.contains(&name) => {
+          return Err(format!("`{{{}}}` is not captured by {}", name, tokens[0]))
+        }
+        None if part.contains(['{', '}']) => {
+          return Err(format!("`{}` should be a whole {{name}} part", part))
+        }
+        _ => (),
+      }
+    }
+    let args = match tokens.len() {
+      3 => None,
+      _ => Some(
+        tokens[3..]
+          .iter()
+          .map(|arg| Arg::parse(arg, &captured))
+          .collect::<Result<Vec<Arg>, String>>()?,
+      ),
+    };
+    Ok(Rule {
+      line,
+      parts,
+      addr: addr.to_string(),
+      args,
+    })
+  }
+
+  // What the parts written `{name}` captured, if the rule matches `addr`.
+  fn captures(&self, addr: &str) -> Option<HashMap<&str, String>> {
+    let segments = addr.strip_prefix('/')?.split('/').collect::<Vec<&str>>();
+    if segments.len() != self.parts.len() {
+      return None;
+    }
+    let mut captures = HashMap::new();
+    for (part, segment) in self.parts.iter().zip(segments) {
+      match part {
+        Part::Capture(name) => {
+          captures.insert(name.as_str(), segment.to_string());
+        }
+        Part::Pattern(pattern) if pattern.matches(&format!("/{}", segment)) => (),
+        Part::Pattern(_) => return None,
+      }
+    }
+    Some(captures)
+  }
+
+  fn transform(&self, msg: &Message, captures: &HashMap<&str, String>) -> Result<Message, String> {
+    let addr = self
+      .addr
+      .split('/')
+      .map(|part| match capture(part) {
+        Some(name) => captures[name].clone(),
+        None => part.to_string(),
+      })
+      .collect::<Vec<String>>()
+      .join("/");
+    let args = match &self.args {
+      Some(args) => args
+        .iter()
+        .map(|arg| arg.value(msg, captures))
+        .collect::<Result<Vec<Type>, String>>()?,
+      None => msg.args.clone(),
+    };
+    Ok(Message { addr, args })
+  }
+}
+
+impl Arg {
+  fn parse(arg: &str, captured: &[&str]) -> Result<Arg, String> {
+    let (source, convert) = match arg.rsplit_once(':') {
+      Some((source, kind)) => (source, Some(Kind::parse(kind)?)),
+      None => (arg, None),
+    };
+    if let Some(name) = capture(source) {
+      if !captured.contains(&name) {
+        return Err(format!("`{{{}}}` is not captured by the pattern", name));
+      }
+      return Ok(Arg {
+        source: Source::Capture(name.to_string()),
+        scale: None,
+        convert,
+      });
+    }
+    let invalid = || {
+      format!(
+        "`{}` should be an argument eg. $0, $0(0..127=0..1) or $0:f, or a capture eg. {{n}}",
+        arg
+      )
+    };
+    let rest = source.strip_prefix('$').ok_or_else(invalid)?;
+    let (index, scale) = match rest.split_once('(') {
+      Some((index, scale)) => {
+        let scale = scale.strip_suffix(')').ok_or_else(invalid)?;
+        (
+          index,
+          Some(parse_scale(scale).map_err(|e| format!("`{}` {}", arg, e))?),
+        )
+      }
+      None => (rest, None),
+    };
+    let index = index.parse::<usize>().map_err(|_| invalid())?;
+    Ok(Arg {
+      source: Source::Arg(index),
+      scale,
+      convert,
+    })
+  }
+
+  fn value(&self, msg: &Message, captures: &HashMap<&str, String>) -> Result<Type, String> {
+    let mut value = match &self.source {
+      Source::Arg(index) => match msg.args.get(*index) {
+        Some(value) => value.clone(),
+        None => {
+          return Err(format!(
+            "it has no argument ${} ({} arguments)",
+            index,
+            msg.args.len()
+          ))
+        }
+      },
+      Source::Capture(name) => Type::String(captures[name.as_str()].clone()),
+    };
+    if let Some((from_min, from_max, to_min, to_max)) = self.scale {
+      let scaled =
+        (number(&value)? - from_min) / (from_max - from_min) * (to_max - to_min) + to_min;
+      value = match value {
+        Type::Double(_) => Type::Double(scaled),
+        _ => Type::Float(scaled as f32),
+      };
+    }
+    match self.convert {
+      Some(kind) => kind.convert(value),
+      None => Ok(value),
+    }
+  }
+}
+
+impl Kind {
+  fn parse(kind: &str) -> Result<Kind, String> {
+    match kind {
+      "i" => Ok(Kind::Int),
+      "h" => Ok(Kind::Long),
+      "f" => Ok(Kind::Float),
+      "d" => Ok(Kind::Double),
+      "s" => Ok(Kind::String),
+      _ => Err(format!("`:{}` should be one of :i, :h, :f, :d or :s", kind)),
+    }
+  }
+
+  fn convert(&self, value: Type) -> Result<Type, String> {
+    // Longs stay as they are, as doubles they would lose their lowest bits.
+    if let (Kind::String, Type::String(_)) | (Kind::Long, Type::Long(_)) = (self, &value) {
+      return Ok(value);
+    }
+    if let Kind::String = self {
+      return Ok(Type::String(match value {
+        Type::Int(v) => v.to_string(),
+        Type::Long(v) => v.to_string(),
+        Type::Float(v) => v.to_string(),
+        Type::Double(v) => v.to_string(),
+        Type::Bool(v) => v.to_string(),
+        Type::Char(v) => v.to_string(),
+        other => return Err(format!("{:?} cannot be converted to a string", other)),
+      }));
+    }
+    let number = number(&value)?;
+    Ok(match self {
+      Kind::Int if number.round() >= i32::MIN as f64 && number.round() <= i32::MAX as f64 => {
+        Type::Int(number.round() as i32)
+      }
+      Kind::Int => return Err(format!("{} does not fit in an int32", number)),
+      // `i64::MAX as f64` is 2^63, one more than `i64::MAX`.
+      Kind::Long if number.round() >= i64::MIN as f64 && number.round() < i64::MAX as f64 => {
+        Type::Long(number.round() as i64)
+      }
+      Kind::Long => return Err(format!("{} does not fit in an int64", number)),
+      Kind::Float => Type::Float(number as f32),
+      Kind::Double => Type::Double(number),
+      Kind::String => unreachable!(),
+    })
+  }
+}
+
+// The name of a part written `{name}`.
+fn capture(part: &str) -> Option<&str> {
+  let name = part.strip_prefix('{')?.strip_suffix('}')?;
+  match !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+    true => Some(name),
+    false => None,
+  }
+}
+
+// Parses `0..127=0..1`.
+fn parse_scale(scale: &str) -> Result<(f64, f64, f64, f64), String> {
+  let range = |range: &str| -> Result<(f64, f64), String> {
+    let (min, max) = range
+      .split_once("..")
+      .ok_or_else(|| "ranges should be written MIN..MAX".to_string())?;
+    match (min.parse::<f64>(), max.parse::<f64>()) {
+      (Ok(min), Ok(max)) if min.is_finite() && max.is_finite() => Ok((min, max)),
+      _ => Err(format!(
+        "`{}` should be a range of numbers eg. 0..127",
+        range
+      )),
+    }
+  };
+  let (from, to) = scale
+    .split_once('=')
+    .ok_or_else(|| "should rescale FROM=TO eg. 0..127=0..1".to_string())?;
+  let (from_min, from_max) = range(from)?;
+  let (to_min, to_max) = range(to)?;
+  if from_min == from_max {
+    return Err(format!("`{}` is an empty range", from));
+  }
+  Ok((from_min, from_max, to_min, to_max))
+}
+
+fn number(value: &Type) -> Result<f64, String> {
+  match value {
+    Type::Int(v) => Ok(*v as f64),
+    Type::Long(v) => Ok(*v as f64),
+    Type::Float(v) => Ok(*v as f64),
+    Type::Double(v) => Ok(*v),
+    Type::Bool(v) => Ok(if *v { 1.0 } else { 0.0 }),
+    Type::String(v) => v
+      .parse::<f64>()
+      .map_err(|_| format!("{:?} is not a number", v)),
+    other => Err(format!("{:?} is not a number", other)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::osc::{msg, Bundle};
+  use super::super::time;
+  use super::*;
+
+  fn apply(rules: &str, packet: Packet) -> (Packet, Vec<String>) {
+    let mut errors = vec![];
+    let packet = Rules::parse(rules).unwrap().apply(packet, &mut errors);
+    (packet, errors)
+  }
+
+  #[test]
+  fn test_rules() {
+    let rules = "\
+      # faders to the mixer\n\
+      /fader/{n} -> /mixer/ch/{n}/gain $0(0..127=0..1)\n\
+      \n\
+      /knob/{n} -> /synth/param {n}:i $0:f # the index first\n\
+      /xy/* -> /pad $1 $0\n\
+      /level/{ch} -> /level/{ch} $0(0..1=0..127):i\n\
+      /rename/* -> /renamed\n\
+      /long -> /long $0:h $1:h\n";
+    let cases = vec![
+      (
+        msg("/fader/3", vec![Type::Int(127)]),
+        msg("/mixer/ch/3/gain", vec![Type::Float(1.0)]),
+      ),
+      (
+        msg("/knob/7", vec![Type::Int(64), Type::Int(1)]),
+        msg("/synth/param", vec![Type::Int(7), Type::Float(64.0)]),
+      ),
+      (
+        msg(
+          "/xy/1",
+          vec![Type::Float(0.25), Type::Float(0.75), Type::Nil],
+        ),
+        msg("/pad", vec![Type::Float(0.75), Type::Float(0.25)]),
+      ),
+      (
+        msg("/level/a", vec![Type::Double(0.5)]),
+        msg("/level/a", vec![Type::Int(64)]),
+      ),
+      (
+        msg("/rename/x", vec![Type::String("kept".into())]),
+        msg("/renamed", vec![Type::String("kept".into())]),
+      ),
+      (
+        msg("/long", vec![Type::Long(i64::MAX), Type::Double(-9.5)]),
+        msg("/long", vec![Type::Long(i64::MAX), Type::Long(-10)]),
+      ),
+      (
+        msg("/fader/3/extra", vec![Type::Int(1)]),
+        msg("/fader/3/extra", vec![Type::Int(1)]),
+      ),
+    ];
+
+    for (input, expected) in cases {
+      let (output, errors) = apply(rules, input.clone().into());
+      assert!(errors.is_empty(), "{:?}", errors);
+      assert_eq!(output, expected.into(), "{:?}", input);
+    }
+  }
+
+  #[test]
+  fn test_rules_in_bundles() {
+    let bundle = Bundle {
+      timetag: time::ntp_now(),
+      content: vec![
+        Packet::from(msg("/fader/1", vec![Type::Int(0)])).into(),
+        Packet::from(msg("/other", vec![])).into(),
+      ],
+    };
+    let (output, errors) = apply("/fader/{n} -> /gain/{n}", Packet::Bundle(bundle.clone()));
+    assert!(errors.is_empty());
+    let mut expected = bundle;
+    expected.content[0] = Packet::from(msg("/gain/1", vec![Type::Int(0)])).into();
+    assert_eq!(output, Packet::Bundle(expected));
+  }
+
+  #[test]
+  fn test_rules_that_do_not_apply() {
+    let rules = "/a -> /b $1\n/c -> /d $0(0..1=0..10)\n/e -> /f $0:h\n";
+    for input in [
+      msg("/a", vec![Type::Int(1)]),
+      msg("/c", vec![Type::String("loud".into())]),
+      msg("/e", vec![Type::String("NaN".into())]),
+      msg("/e", vec![Type::Double(1e19)]),
+      msg("/e", vec![Type::Float(-1e19)]),
+    ] {
+      let (output, errors) = apply(rules, input.clone().into());
+      assert_eq!(output, input.into());
+      assert_eq!(errors.len(), 1, "{:?}", errors);
+    }
+  }
+
+  #[test]
+  fn test_invalid_rules() {
+    let invalid = [
+      ("/a /b", "should be written"),
+      ("a -> /b", "should start with /"),
+      ("/a -> b", "should start with /"),
+      ("/a -> /{n}", "not captured"),
+      ("/a/{n} -> /b {m}", "not captured"),
+      ("/a -> /b $x", "should be an argument"),
+      ("/a -> /b $0:q", ":i"),
+      ("/a -> /b $0(1..1=0..1)", "empty range"),
+      ("/a -> /b $0(0..1)", "FROM=TO"),
+      ("/a//b -> /c", "empty part"),
+    ];
+
+    for (rule, reason) in invalid.iter() {
+      let err = Rules::parse(rule).unwrap_err();
+      assert!(err.starts_with("1: "), "{}: {}", rule, err);
+      assert!(err.contains(reason), "{}: {}", rule, err);
+    }
+    let err = Rules::parse("# header\n\n/a -> /b\n/a -> b\n").unwrap_err();
+    assert!(err.starts_with("4: "), "{}", err);
+  }
+}
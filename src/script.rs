@@ -0,0 +1,114 @@
+//! Script files sent by `oscd run`, one line at a time.
+//!
+//! Every line of a script is either a message or a bundle, written the same way as in the send
+//! mode, or a directive:
+//!
+//! ```text
+//! # start the synth, then change its frequency
+//! /s_new "default" 1000 0 0 "freq" 440
+//! wait 500ms
+//! /n_set 1000 "freq" 880
+//! #bundle @now+1s { /n_set 1000 "amp" 0; /n_free 1000 }
+//! ```
+//!
+//! - `wait <duration>` pauses before sending the next line, eg. `wait 500ms` or `wait 1.5s`,
+//! - a `#` followed by a space (or alone) starts a comment line, `#bundle` and colours such as
+//!   `#2F14DF2A` are not comments,
+//! - blank lines are ignored.
+
+use super::time;
+use std::time::Duration;
+
+/// What a line of a script does.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+  /// Sends the line, a message or a bundle.
+  Send(String),
+  Wait(Duration),
+}
+
+impl Step {
+  /// Parses a line of a script, `None` for blank lines and comments.
+  ///
+  /// Errors come with the column (1 for the first character) they were found at. Messages are
+  /// not checked here, they go through the same lexer and parser as in the send mode.
+  pub fn parse(line: &str) -> Result<Option<Step>, (usize, String)> {
+    let trimmed = line.trim();
+    let indent = line.len() - line.trim_start().len();
+    if trimmed.is_empty() || is_comment(trimmed) {
+      return Ok(None);
+    }
+    let duration = match trimmed.strip_prefix("wait") {
+      Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => rest.trim(),
+      _ => return Ok(Some(Step::Send(line.to_string()))),
+    };
+    // The duration ends the line.
+    let offset = line.trim_end().len() - duration.len();
+    if duration.is_empty() {
+      return Err((
+        column(line, indent + "wait".len()),
+        "`wait` should be followed by a duration, eg. wait 500ms".to_string(),
+      ));
+    }
+    time::parse_duration(duration)
+      .map(|duration| Some(Step::Wait(duration)))
+      .map_err(|e| (column(line, offset), e))
+  }
+}
+
+// `#bundle` and colours, eg. `#2F14DF2A`, start with `#` as well.
+fn is_comment(line: &str) -> bool {
+  match line.strip_prefix('#') {
+    Some(rest) => rest.is_empty() || rest.starts_with(char::is_whitespace),
+    None => false,
+  }
+}
+
+/// The column of the character at byte `offset` of `line`, 1 for the first character.
+pub fn column(line: &str, offset: usize) -> usize {
+  line.char_indices().take_while(|(i, _)| *i < offset).count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_steps() {
+    let steps = [
+      (
+        "/s_new \"default\" 1000",
+        Some(Step::Send("/s_new \"default\" 1000".into())),
+      ),
+      ("  wait 500ms", Some(Step::Wait(Duration::from_millis(500)))),
+      ("wait 1.5s ", Some(Step::Wait(Duration::from_millis(1500)))),
+      ("", None),
+      ("   ", None),
+      ("# a comment", None),
+      ("#", None),
+      (
+        "#bundle { /a 1 }",
+        Some(Step::Send("#bundle { /a 1 }".into())),
+      ),
+      ("waiting 1", Some(Step::Send("waiting 1".into()))),
+    ];
+
+    for (line, step) in steps.iter() {
+      assert_eq!(Step::parse(line), Ok(step.clone()), "{}", line);
+    }
+  }
+
+  #[test]
+  fn test_invalid_waits() {
+    assert_eq!(Step::parse("  wait 5 parsecs").unwrap_err().0, 8);
+    assert_eq!(Step::parse("wait").unwrap_err().0, 5);
+    assert_eq!(Step::parse("wait  soon").unwrap_err().0, 7);
+  }
+
+  #[test]
+  fn test_columns() {
+    assert_eq!(column("/a 1", 0), 1);
+    assert_eq!(column("/a 1", 3), 4);
+    assert_eq!(column("/é \"x", 4), 4);
+  }
+}
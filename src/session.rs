@@ -0,0 +1,192 @@
+//! Recording of monitored traffic into session files.
+//!
+//! A session file is plain UTF-8 text. The first line is the header `oscd-session 1`, every
+//! following line is one received datagram made of four fields separated by a single space:
+//!
+//! ```text
+//! oscd-session 1
+//! <timestamp> <local port> <source address> <bytes>
+//! 1729195200123456 57110 127.0.0.1:57120 2f615f6e65770000
+//! ```
+//!
+//! - `<timestamp>` is the time the datagram was received, in microseconds since the Unix epoch,
+//! - `<local port>` is the port the datagram was received on,
+//! - `<source address>` is the socket address of the sender, eg. `10.0.0.5:57120` or
+//!   `[fe80::1]:57120`,
+//! - `<bytes>` is the raw datagram, hex encoded with two lowercase digits per byte.
+//!
+//! Each line is flushed as soon as it is written, so a crash loses at most the datagram being
+//! recorded at that moment. Blank lines are ignored when a session is read back.
+
+use super::hex;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The first line of every session file.
+pub const HEADER: &str = "oscd-session 1";
+
+/// A single datagram as it was received.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+  /// Microseconds since the Unix epoch.
+  pub timestamp: u64,
+  pub local_port: u16,
+  pub source: SocketAddr,
+  pub bytes: Vec<u8>,
+}
+
+impl Record {
+  /// A record of `bytes` received right now.
+  pub fn now(local_port: u16, source: SocketAddr, bytes: Vec<u8>) -> Self {
+    let timestamp = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_micros() as u64)
+      .unwrap_or(0);
+    Record {
+      timestamp,
+      local_port,
+      source,
+      bytes,
+    }
+  }
+
+  /// Parses a single line of a session file.
+  pub fn parse(line: &str) -> Result<Record, String> {
+    let fields = line.split(' ').collect::<Vec<&str>>();
+    if fields.len() != 4 {
+      return Err(format!(
+        "expected 4 space separated fields, found {}",
+        fields.len()
+      ));
+    }
+    let timestamp = fields[0]
+      .parse::<u64>()
+      .map_err(|_| format!("`{}` is not a valid timestamp", fields[0]))?;
+    let local_port = fields[1]
+      .parse::<u16>()
+      .map_err(|_| format!("`{}` is not a valid port", fields[1]))?;
+    let source = fields[2]
+      .parse::<SocketAddr>()
+      .map_err(|_| format!("`{}` is not a valid socket address", fields[2]))?;
+    let bytes = hex::decode(fields[3])
+      .ok_or_else(|| format!("`{}` is not valid hex encoded bytes", fields[3]))?;
+    Ok(Record {
+      timestamp,
+      local_port,
+      source,
+      bytes,
+    })
+  }
+}
+
+/// Reads all the records of the session file at `path`, in the order they were recorded.
+///
+/// Errors describe the offending line, eg. `show.session:3: ...`.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Record>, String> {
+  let path = path.as_ref();
+  let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+  let mut lines = content.lines().enumerate();
+  match lines.next() {
+    Some((_, HEADER)) => (),
+    _ => {
+      return Err(format!(
+        "{}:1: not a session file, expected `{}`",
+        path.display(),
+        HEADER
+      ))
+    }
+  }
+  lines
+    .filter(|(_, line)| !line.trim().is_empty())
+    .map(|(i, line)| {
+      Record::parse(line).map_err(|e| format!("{}:{}: {}", path.display(), i + 1, e))
+    })
+    .collect()
+}
+
+/// Appends records to a session file.
+pub struct Recorder {
+  writer: BufWriter<File>,
+}
+
+impl Recorder {
+  /// Creates (or truncates) the session file at `path` and writes its header.
+  pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}", HEADER)?;
+    writer.flush()?;
+    Ok(Recorder { writer })
+  }
+
+  /// Writes the record as a new line and flushes it to disk.
+  pub fn record(&mut self, record: &Record) -> Result<(), io::Error> {
+    write!(
+      self.writer,
+      "{} {} {} ",
+      record.timestamp, record.local_port, record.source
+    )?;
+    writeln!(self.writer, "{}", hex::encode(&record.bytes))?;
+    self.writer.flush()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_record_lines() {
+    let path = std::env::temp_dir().join(format!("oscd-record-{}.session", std::process::id()));
+    let mut recorder = Recorder::create(&path).unwrap();
+    let records = [
+      Record {
+        timestamp: 1_729_195_200_123_456,
+        local_port: 57110,
+        source: "127.0.0.1:57120".parse().unwrap(),
+        bytes: b"/a\0\0,\0\0\0".to_vec(),
+      },
+      Record {
+        timestamp: 1_729_195_200_223_456,
+        local_port: 9000,
+        source: "[fe80::1]:9001".parse().unwrap(),
+        bytes: vec![0xff],
+      },
+    ];
+    for record in records.iter() {
+      recorder.record(record).unwrap();
+    }
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    let read_back = read(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+      content,
+      "oscd-session 1\n\
+       1729195200123456 57110 127.0.0.1:57120 2f6100002c000000\n\
+       1729195200223456 9000 [fe80::1]:9001 ff\n"
+    );
+    assert_eq!(read_back.unwrap(), records);
+  }
+
+  #[test]
+  fn test_invalid_lines() {
+    let invalid = [
+      ("1 2 127.0.0.1:9000", "expected 4"),
+      ("x 2 127.0.0.1:9000 ff", "timestamp"),
+      ("1 70000 127.0.0.1:9000 ff", "port"),
+      ("1 2 127.0.0.1 ff", "socket address"),
+      ("1 2 127.0.0.1:9000 fff", "hex"),
+      ("1 2 127.0.0.1:9000 zz", "hex"),
+      ("1 2 127.0.0.1:9000 +f", "hex"),
+      ("1 2 127.0.0.1:9000 é", "hex"),
+    ];
+
+    for (line, reason) in invalid.iter() {
+      let err = Record::parse(line).unwrap_err();
+      assert!(err.contains(reason), "{}: {}", line, err);
+    }
+  }
+}
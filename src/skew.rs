@@ -0,0 +1,137 @@
+//! Running statistics of how far ahead of the local clock the bundle timetags of each source are.
+//!
+//! A sender scheduling its bundles a fixed latency ahead shows a steady lead, a lead that keeps
+//! growing or shrinking over time means the clocks of the two machines drift apart.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+/// The lead statistics of every source seen so far.
+#[derive(Debug, Default)]
+pub struct Skew {
+  sources: HashMap<SocketAddr, Lead>,
+}
+
+/// Lead of the timetags of a single source, in seconds, negative when they arrive late.
+#[derive(Clone, Debug)]
+pub struct Lead {
+  count: usize,
+  first_received: SystemTime,
+  last: f64,
+  min: f64,
+  max: f64,
+  sum: f64,
+  // Sums for the least squares slope of the lead over the time of reception.
+  sum_t: f64,
+  sum_tt: f64,
+  sum_ty: f64,
+}
+
+impl Skew {
+  /// Adds a timetag of `source` that was `lead` seconds ahead of the local clock when it was
+  /// received, and returns the updated statistics of that source.
+  pub fn record(&mut self, source: SocketAddr, lead: f64, received: SystemTime) -> &Lead {
+    let stats = self.sources.entry(source).or_insert_with(|| Lead {
+      count: 0,
+      first_received: received,
+      last: lead,
+      min: lead,
+      max: lead,
+      sum: 0.0,
+      sum_t: 0.0,
+      sum_tt: 0.0,
+      sum_ty: 0.0,
+    });
+    let t = received
+      .duration_since(stats.first_received)
+      .map(|elapsed| elapsed.as_secs_f64())
+      .unwrap_or(0.0);
+    stats.count += 1;
+    stats.last = lead;
+    stats.min = stats.min.min(lead);
+    stats.max = stats.max.max(lead);
+    stats.sum += lead;
+    stats.sum_t += t;
+    stats.sum_tt += t * t;
+    stats.sum_ty += t * lead;
+    stats
+  }
+}
+
+impl Lead {
+  pub fn mean(&self) -> f64 {
+    self.sum / self.count as f64
+  }
+
+  /// How much the lead changes per second of reception, `None` until timetags were received at
+  /// two different times.
+  pub fn drift(&self) -> Option<f64> {
+    let n = self.count as f64;
+    let denominator = n * self.sum_tt - self.sum_t * self.sum_t;
+    if self.count < 2 || denominator.abs() < f64::EPSILON {
+      return None;
+    }
+    Some((n * self.sum_ty - self.sum_t * self.sum) / denominator)
+  }
+}
+
+impl fmt::Display for Lead {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "last {}, mean {}, min {}, max {}",
+      millis(self.last),
+      millis(self.mean()),
+      millis(self.min),
+      millis(self.max)
+    )?;
+    if let Some(drift) = self.drift() {
+      write!(f, ", drift {:+.3} ms/min", drift * 60_000.0)?;
+    }
+    let plural = if self.count == 1 { "" } else { "s" };
+    write!(f, " over {} timetag{}", self.count, plural)
+  }
+}
+
+fn millis(seconds: f64) -> String {
+  format!("{:+.1} ms", seconds * 1_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Duration;
+
+  #[test]
+  fn test_lead_statistics() {
+    let source = "10.0.0.5:57120".parse().unwrap();
+    let start = SystemTime::now();
+    let mut skew = Skew::default();
+    // The lead grows by 1 ms every 10 s, ie. 6 ms/min.
+    for i in 0..4 {
+      skew.record(
+        source,
+        0.1 + i as f64 * 0.001,
+        start + Duration::from_secs(i * 10),
+      );
+    }
+    let lead = skew.record(source, 0.104, start + Duration::from_secs(40));
+
+    assert!((lead.mean() - 0.102).abs() < 1e-9);
+    assert!((lead.drift().unwrap() * 60.0 - 0.006).abs() < 1e-9);
+    assert_eq!(
+      lead.to_string(),
+      "last +104.0 ms, mean +102.0 ms, min +100.0 ms, max +104.0 ms, drift +6.000 ms/min over 5 timetags"
+    );
+
+    let other = "10.0.0.6:57120".parse().unwrap();
+    let late = skew.record(other, -3.0, start);
+    assert_eq!(late.drift(), None);
+    assert_eq!(
+      late.to_string(),
+      "last -3000.0 ms, mean -3000.0 ms, min -3000.0 ms, max -3000.0 ms over 1 timetag"
+    );
+  }
+}
@@ -22,6 +22,23 @@ pub struct TableSuccess {
   osc_message: String,
 }
 
+#[derive(Tabled)]
+pub struct TableReceived {
+  source: String,
+  packet_size: String,
+  osc_address: String,
+  type_tags: String,
+  osc_message: String,
+}
+
+#[derive(Tabled)]
+pub struct TableMalformed {
+  source: String,
+  packet_size: String,
+  decode_error: String,
+  reason: String,
+}
+
 impl TableError {
   pub fn new(range: String, input: String, message: String, msg_type: String) -> Self {
     Self {
@@ -43,6 +60,35 @@ impl TableSuccess {
   }
 }
 
+impl TableReceived {
+  pub fn new(
+    source: String,
+    packet_size: String,
+    osc_address: String,
+    type_tags: String,
+    osc_message: String,
+  ) -> Self {
+    Self {
+      source,
+      packet_size,
+      osc_address,
+      type_tags,
+      osc_message,
+    }
+  }
+}
+
+impl TableMalformed {
+  pub fn new(source: String, packet_size: String, decode_error: String, reason: String) -> Self {
+    Self {
+      source,
+      packet_size,
+      decode_error,
+      reason,
+    }
+  }
+}
+
 type TableTheme = Settings<
   Settings<Settings<Settings, Style<On, On, On, On, (), On, 1, 0>>, Padding>,
   ModifyList<FirstRow, Alignment>,
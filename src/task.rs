@@ -1,5 +1,5 @@
 use colored::*;
-use rosc::OscType;
+use rosc::OscPacket;
 use rustyline::completion::FilenameCompleter;
 use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
 use rustyline::hint::HistoryHinter;
@@ -7,19 +7,41 @@ use rustyline::validate::MatchingBracketValidator;
 use rustyline::{CompletionType, Config, EditMode, Editor};
 use rustyline_derive::{Completer, Helper, Hinter, Validator};
 use std::borrow::Cow::{self, Borrowed, Owned};
+use std::collections::HashMap;
 use std::fmt;
-use std::io::{stdout, Write};
+use std::fs;
+use std::io::{stdin, stdout, BufRead, ErrorKind, Write};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use tabled::settings::object::{Column, Columns, Row, Rows};
 use tabled::settings::Width;
 use tabled::Table;
 use termion::screen::*;
 
-use super::analyser::lexer::Lexer;
-use super::analyser::parser::{parse_message, Expr, Literal, Parser, Stmt};
+use super::analyser::lexer::{Error as LexError, Lexer};
+use super::analyser::parser::{parse_bundle_timetag, parse_message, Expr, Literal, Parser, Stmt};
 use super::analyser::token::Tokens;
+use super::csv::{Exporter, Layout};
+use super::filter::Filter;
+use super::json;
 use super::osc;
-use super::table::{TableError, TableSuccess, THEME};
+use super::osc::address::Pattern;
+use super::osc::multicast::Group;
+use super::ports::Proto;
+use super::proxy::{self, Faults, Random, Route};
+use super::render;
+use super::rewrite::{rewrite_packet, Rewrite};
+use super::rules::Rules;
+use super::script::{self, Step};
+use super::session::{self, Record, Recorder};
+use super::skew::Skew;
+use super::table::{TableError, TableMalformed, THEME};
+use super::time;
+use super::{DEFAULT_BIND, DEFAULT_PORT};
 
 #[derive(Helper, Completer, Hinter, Validator)]
 pub struct MyHelper {
@@ -80,14 +102,1229 @@ impl fmt::Display for Task {
   }
 }
 
-pub fn monitor(port: u16) {
-  let recv = osc::receiver(port).expect("Could not connect to receiver address");
+/// The ways a non-interactive `oscd` invocation can fail, each with its own process exit code so
+/// that scripts can tell them apart (`2` is left to `clap` for invalid command-line usage).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+  /// The input could not be lexed into tokens.
+  Lex,
+  /// The tokens did not form a valid OSC message.
+  Parse,
+  /// A socket could not be bound, connected or written to.
+  Io,
+  /// No reply was received in time.
+  Timeout,
+}
+
+impl Failure {
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      Failure::Lex => 3,
+      Failure::Parse => 4,
+      Failure::Io => 5,
+      Failure::Timeout => 6,
+    }
+  }
+}
+
+/// Everything `monitor` needs to know about what to listen to, what to show and what to keep.
+pub struct MonitorOptions {
+  pub bind: String,
+  /// The ports to listen on, at least one.
+  pub ports: Vec<Port>,
+  /// Multicast groups to join, on every UDP port.
+  pub groups: Vec<Group>,
+  /// Session file every received datagram is recorded to.
+  pub record: Option<PathBuf>,
+  /// Where the messages shown are exported as CSV.
+  pub csv: Option<Layout>,
+  pub output: Output,
+}
+
+/// How the monitor shows the packets it receives.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Output {
+  /// Coloured tables, for people.
+  #[default]
+  Table,
+  /// One JSON object per line, for tools, see `json`. Everything else is printed to stderr.
+  Json,
+}
+
+impl Output {
+  // Prints a line about the monitor itself rather than a packet it received, out of the way of
+  // JSON Lines.
+  fn notice<D: fmt::Display>(&self, line: D) {
+    match self {
+      Output::Table => println!("{}", line),
+      Output::Json => eprintln!("{}", line),
+    }
+  }
+}
+
+impl Default for MonitorOptions {
+  fn default() -> Self {
+    MonitorOptions {
+      bind: DEFAULT_BIND.to_string(),
+      ports: vec![Port::default()],
+      groups: vec![],
+      record: None,
+      csv: None,
+      output: Output::default(),
+    }
+  }
+}
+
+/// A port the monitor listens on, along with which of the packets it receives are shown.
+pub struct Port {
+  pub port: u16,
+  pub transport: osc::Transport,
+  pub filter: Filter,
+  /// Colour of the tag showing which port a packet arrived on.
+  pub color: Option<Color>,
+}
+
+impl Default for Port {
+  fn default() -> Self {
+    Port {
+      port: DEFAULT_PORT,
+      transport: osc::Transport::Udp,
+      filter: Filter::default(),
+      color: None,
+    }
+  }
+}
+
+pub fn monitor(options: MonitorOptions) -> Result<(), Failure> {
+  let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+  let MonitorOptions {
+    bind,
+    ports,
+    groups,
+    record,
+    csv,
+    output,
+  } = options;
+  let over_udp = ports
+    .iter()
+    .any(|port| port.transport == osc::Transport::Udp);
+  if let (false, Some(group)) = (over_udp, groups.first()) {
+    output.notice(format!(
+      "{}{}",
+      failed_log_prefix,
+      format!("cannot join {} over TCP, multicast is UDP only", group)
+        .white()
+        .dimmed()
+    ));
+    return Err(Failure::Io);
+  }
+  // Packets are only tagged with their port when there is a choice.
+  let tagged = ports.len() > 1 || ports.iter().any(|port| port.color.is_some());
+  let mut input = Input::new(output);
+  for port in ports.iter() {
+    if let Err(e) = input.bind(&bind, port, &groups, tagged) {
+      output.notice(format!(
+        "{}{}",
+        failed_log_prefix,
+        format!("could not listen on {}:{}, {}", bind, port.port, e)
+          .white()
+          .dimmed()
+      ));
+      return Err(Failure::Io);
+    }
+  }
+  for group in groups.iter() {
+    output.notice(format!("joined multicast group {}", group).dimmed());
+  }
+  let mut recorder = match record {
+    Some(path) => match Recorder::create(&path) {
+      Ok(recorder) => {
+        output.notice(format!("recording to {}", path.display()).dimmed());
+        Some(recorder)
+      }
+      Err(e) => {
+        output.notice(format!(
+          "{}{}",
+          failed_log_prefix,
+          format!("could not record to {}, {}", path.display(), e)
+            .white()
+            .dimmed()
+        ));
+        return Err(Failure::Io);
+      }
+    },
+    None => None,
+  };
+  let mut exporter = match csv {
+    Some(layout) => {
+      let path = match &layout {
+        Layout::PerAddress(dir) => dir.display().to_string(),
+        Layout::Long(path) => path.display().to_string(),
+      };
+      match Exporter::create(layout) {
+        Ok(exporter) => {
+          output.notice(format!("exporting to {}", path).dimmed());
+          Some(exporter)
+        }
+        Err(e) => {
+          output.notice(format!(
+            "{}{}",
+            failed_log_prefix,
+            format!("could not export to {}, {}", path, e)
+              .white()
+              .dimmed()
+          ));
+          return Err(Failure::Io);
+        }
+      }
+    }
+    None => None,
+  };
+
+  let mut skew = Skew::default();
   loop {
-    println!("{:?}", recv.recv().unwrap());
+    match input.recv() {
+      Ok(None) => (),
+      Ok(Some((index, bytes, source))) => {
+        let received = SystemTime::now();
+        let local = &input.ports[index];
+        if let Some(rec) = recorder.as_mut() {
+          if let Err(e) = rec.record(&Record::now(local.port, source, bytes.clone())) {
+            output.notice(format!(
+              "{}{}",
+              failed_log_prefix,
+              format!("could not record, recording stopped, {}", e)
+                .white()
+                .dimmed()
+            ));
+            recorder = None;
+          }
+        }
+        let decoded = osc::decode(&bytes).map(|packet| ports[index].filter.apply(packet, source));
+        if let (Ok(Some(packet)), Some(csv)) = (&decoded, exporter.as_mut()) {
+          if let Err(e) = csv.export(packet, received) {
+            output.notice(format!(
+              "{}{}",
+              failed_log_prefix,
+              format!("could not export to CSV, export stopped, {}", e)
+                .white()
+                .dimmed()
+            ));
+            exporter = None;
+          }
+        }
+        if let Output::Json = output {
+          let origin = json::Origin {
+            received,
+            source,
+            port: local.port,
+            proto: local.proto,
+            size: bytes.len(),
+          };
+          let line = match decoded {
+            Ok(Some(packet)) => json::received(&origin, &packet),
+            Ok(None) => continue,
+            Err(e) => json::malformed(
+              &origin,
+              &bytes,
+              format!("{:?}", e),
+              malformed_reason(&bytes),
+            ),
+          };
+          println!("{}", line);
+          continue;
+        }
+        match decoded {
+          Ok(Some(packet)) => {
+            print_packet(&packet, source, bytes.len(), received, local.tag.as_ref());
+            print_skew(&mut skew, &packet, source, received);
+          }
+          Ok(None) => (),
+          Err(e) => print_malformed(&bytes, source, e, local.tag.as_ref()),
+        }
+      }
+      Err(e) => {
+        output.notice(format!(
+          "{}{}",
+          failed_log_prefix,
+          format!("could not receive, {}", e).white().dimmed()
+        ));
+        if is_fatal(&e) {
+          return Err(Failure::Io);
+        }
+      }
+    }
+  }
+}
+
+// Whether receiving failed for good, eg. once every listening thread stopped, rather than
+// because of a single datagram, eg. an ICMP port unreachable reported by the socket.
+fn is_fatal(error: &osc::CommunicationError) -> bool {
+  match error {
+    osc::CommunicationError::Io(e) => !matches!(
+      e.kind(),
+      ErrorKind::Interrupted
+        | ErrorKind::WouldBlock
+        | ErrorKind::TimedOut
+        | ErrorKind::ConnectionRefused
+        | ErrorKind::ConnectionReset
+    ),
+    osc::CommunicationError::Osc(_) => false,
+    osc::CommunicationError::Poisoned => true,
+  }
+}
+
+// What `monitor` listens to. Every socket is read from a thread of its own, whatever they
+// receive is gathered in `incoming` along with the index of the port it arrived on.
+struct Input {
+  ports: Vec<Local>,
+  sender: Option<mpsc::Sender<(usize, Received)>>,
+  incoming: mpsc::Receiver<(usize, Received)>,
+  output: Output,
+}
+
+// A port listened on, along with its tag when packets are tagged.
+struct Local {
+  port: u16,
+  proto: Proto,
+  tag: Option<ColoredString>,
+}
+
+type Received = Result<Incoming, osc::CommunicationError>;
+
+enum Incoming {
+  Datagram(Vec<u8>, SocketAddr),
+  Tcp(osc::tcp::Event),
+}
+
+impl Input {
+  fn new(output: Output) -> Input {
+    let (sender, incoming) = mpsc::channel();
+    Input {
+      ports: vec![],
+      sender: Some(sender),
+      incoming,
+      output,
+    }
+  }
+
+  // Listening on the unspecified IPv6 address `::` also listens on `0.0.0.0`, unless the IPv6
+  // socket is dual-stack and receives IPv4 already, in which case it joins IPv4 groups as well.
+  // Groups are only joined over UDP.
+  fn bind(
+    &mut self,
+    bind: &str,
+    port: &Port,
+    groups: &[Group],
+    tagged: bool,
+  ) -> Result<(), std::io::Error> {
+    let addr = match (bind, port.port).to_socket_addrs()?.next() {
+      Some(addr) => addr,
+      None => return Err(ErrorKind::NotFound.into()),
+    };
+    let groups = match port.transport {
+      osc::Transport::Udp => groups,
+      osc::Transport::Tcp(_) => &[],
+    };
+    if let Some(group) = groups
+      .iter()
+      .find(|group| group.addr.is_ipv6() && addr.is_ipv4())
+    {
+      return Err(std::io::Error::new(
+        ErrorKind::InvalidInput,
+        format!("cannot join {} on an IPv4 address, try --bind ::", group),
+      ));
+    }
+    let sender = match &self.sender {
+      Some(sender) => sender.clone(),
+      None => return Err(ErrorKind::BrokenPipe.into()),
+    };
+    let index = self.ports.len();
+    let socket = Socket::bind(addr, port.transport)?;
+    let local_port = socket.local_port()?;
+    let mut joined_by_ipv4 = false;
+    if addr.ip() == IpAddr::from(osc::default_ipv6_addr()) {
+      // Fails with `AddrInUse` on dual-stack sockets.
+      let ipv4 = SocketAddr::new(osc::default_ipv4_addr().into(), local_port);
+      if let Ok(ipv4) = Socket::bind(ipv4, port.transport) {
+        for group in groups.iter().filter(|group| group.addr.is_ipv4()) {
+          ipv4.join(group)?;
+        }
+        joined_by_ipv4 = true;
+        ipv4.listen(index, sender.clone());
+      }
+    }
+    for group in groups {
+      if !(joined_by_ipv4 && group.addr.is_ipv4()) {
+        socket.join(group)?;
+      }
+    }
+    socket.listen(index, sender);
+    let proto = match port.transport {
+      osc::Transport::Udp => Proto::Udp,
+      osc::Transport::Tcp(_) => Proto::Tcp,
+    };
+    let tag = tagged.then(|| {
+      let tag = format!("{}/{}", local_port, proto);
+      match port.color {
+        Some(color) => tag.color(color),
+        None => tag.dimmed(),
+      }
+    });
+    self.ports.push(Local {
+      port: local_port,
+      proto,
+      tag,
+    });
+    Ok(())
+  }
+
+  // Waits for the next datagram or frame and the index of the port it arrived on, printing TCP
+  // connections opening and closing in the meantime, which yield `None`, as do ports that can no
+  // longer be listened on. The other ports are still listened on then.
+  fn recv(&mut self) -> Result<Option<(usize, Vec<u8>, SocketAddr)>, osc::CommunicationError> {
+    // Only the listening threads are left to send, the channel closes when they are all gone.
+    self.sender.take();
+    let (index, incoming) = self.incoming.recv().map_err(|_| {
+      std::io::Error::new(ErrorKind::BrokenPipe, "stopped listening on every socket")
+    })?;
+    let event = match incoming {
+      Ok(Incoming::Datagram(bytes, source)) => return Ok(Some((index, bytes, source))),
+      Ok(Incoming::Tcp(event)) => event,
+      // The thread listening on the port stopped along with the error.
+      Err(e) if is_fatal(&e) => {
+        let local = &self.ports[index];
+        self.output.notice(format!(
+          "{}{}",
+          "❌ [FAILED]: ".to_string().red().dimmed(),
+          format!(
+            "could not receive on {}/{}, stopped listening on it, {}",
+            local.port, local.proto, e
+          )
+          .white()
+          .dimmed()
+        ));
+        return Ok(None);
+      }
+      Err(e) => return Err(e),
+    };
+    // Connections are told apart by the port they were accepted on when packets are tagged.
+    let on = match &self.ports[index].tag {
+      Some(tag) => format!(" on {}", tag),
+      None => String::new(),
+    };
+    match event {
+      osc::tcp::Event::Frame(bytes, source) => return Ok(Some((index, bytes, source))),
+      osc::tcp::Event::Connected(source) => self.output.notice(format!(
+        "{}{}",
+        format!("{} connected", source).dimmed(),
+        on
+      )),
+      osc::tcp::Event::Detected(source, Some(framing)) => {
+        let framing = match framing {
+          osc::tcp::Framing::LengthPrefix => "an int32 size prefix (OSC 1.0)",
+          _ => "SLIP (OSC 1.1)",
+        };
+        self.output.notice(format!(
+          "{}{}",
+          format!("{} frames packets with {}", source, framing).dimmed(),
+          on
+        ))
+      }
+      osc::tcp::Event::Detected(source, None) => self.output.notice(format!(
+        "{}{}",
+        format!(
+          "{} uses neither SLIP nor size prefix framing, showing the bytes as they arrive",
+          source
+        )
+        .dimmed(),
+        on
+      )),
+      osc::tcp::Event::Closed(source, None) => self.output.notice(format!(
+        "{}{}",
+        format!("{} disconnected", source).dimmed(),
+        on
+      )),
+      osc::tcp::Event::Closed(source, Some(e)) => self.output.notice(format!(
+        "{}{}{}",
+        "❌ [FAILED]: ".to_string().red().dimmed(),
+        format!("connection from {} closed, {}", source, e)
+          .white()
+          .dimmed(),
+        on
+      )),
+      osc::tcp::Event::AcceptFailed(e) => self.output.notice(format!(
+        "{}{}{}",
+        "❌ [FAILED]: ".to_string().red().dimmed(),
+        format!("could not accept a connection, {}", e)
+          .white()
+          .dimmed(),
+        on
+      )),
+    }
+    Ok(None)
+  }
+}
+
+// A socket the monitor listens on, bound but not yet read from.
+enum Socket {
+  Udp(osc::Receiver),
+  Tcp(osc::tcp::Listener),
+}
+
+impl Socket {
+  fn bind(addr: SocketAddr, transport: osc::Transport) -> Result<Socket, std::io::Error> {
+    match transport {
+      osc::Transport::Udp => {
+        osc::Receiver::bind_to_with_mtu(addr, osc::recv::MAX_UDP_PAYLOAD).map(Socket::Udp)
+      }
+      osc::Transport::Tcp(framing) => osc::tcp::Listener::bind(addr, framing).map(Socket::Tcp),
+    }
+  }
+
+  fn local_port(&self) -> Result<u16, std::io::Error> {
+    match self {
+      Socket::Udp(recv) => Ok(recv.local_addr()?.port()),
+      Socket::Tcp(listener) => Ok(listener.local_addr().port()),
+    }
+  }
+
+  fn join(&self, group: &Group) -> Result<(), std::io::Error> {
+    match self {
+      Socket::Udp(recv) => recv
+        .join(group)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("could not join {}, {}", group, e))),
+      Socket::Tcp(_) => Err(ErrorKind::InvalidInput.into()),
+    }
+  }
+
+  // Forwards everything received to `incoming` from a new thread, along with `index`, until
+  // receiving fails for good.
+  fn listen(self, index: usize, incoming: mpsc::Sender<(usize, Received)>) {
+    match self {
+      Socket::Udp(recv) => thread::spawn(move || loop {
+        let received = recv
+          .recv_bytes()
+          .map(|(bytes, source)| Incoming::Datagram(bytes, osc::unmapped(source)));
+        let stopped = matches!(&received, Err(e) if is_fatal(e));
+        if incoming.send((index, received)).is_err() || stopped {
+          break;
+        }
+      }),
+      Socket::Tcp(listener) => thread::spawn(move || loop {
+        let received = listener.recv().map(Incoming::Tcp);
+        let stopped = matches!(&received, Err(e) if is_fatal(e));
+        if incoming.send((index, received)).is_err() || stopped {
+          break;
+        }
+      }),
+    };
+  }
+}
+
+/// Prints a datagram that could not be decoded, along with a hex dump of its bytes and a
+/// best-effort explanation of where decoding broke, and the tag of the port it arrived on if any.
+pub fn print_malformed(
+  bytes: &[u8],
+  source: SocketAddr,
+  error: osc::Error,
+  tag: Option<&ColoredString>,
+) {
+  let malformed_log_prefix = "\n⚠️  [MALFORMED]: ".yellow().dimmed();
+  let data = vec![TableMalformed::new(
+    source.to_string(),
+    format!("{} bytes", bytes.len()),
+    format!("{:?}", error),
+    malformed_reason(bytes),
+  )];
+  let mut table = Table::new(data);
+  table.with(THEME);
+  print_log_prefix(malformed_log_prefix, tag);
+  println!("{table}");
+  println!("{}\n", osc::inspect::hex_dump(bytes).dimmed());
+}
+
+// Where, and why, the encoding of a datagram that could not be decoded broke.
+fn malformed_reason(bytes: &[u8]) -> String {
+  match osc::inspect::diagnose(bytes) {
+    Some(diagnosis) => diagnosis.to_string(),
+    None => "the packet structure looks valid, but its content was rejected".to_string(),
+  }
+}
+
+// Updates the lead statistics of `source` with the bundle timetags of `packet` and prints them,
+// if it has any.
+fn print_skew(skew: &mut Skew, packet: &osc::Packet, source: SocketAddr, received: SystemTime) {
+  let timetags = render::bundle_timetags(packet);
+  let mut lead = None;
+  for timetag in timetags {
+    lead = Some(
+      skew
+        .record(source, time::lead(timetag, received), received)
+        .clone(),
+    );
+  }
+  if let Some(lead) = lead {
+    println!(
+      "{}",
+      format!("⏱  timetag lead of {}: {}\n", source, lead).dimmed()
+    );
+  }
+}
+
+/// Prints a received packet as a table, the same way a sent packet is shown, along with the tag of
+/// the port it arrived on if any.
+pub fn print_packet(
+  packet: &osc::Packet,
+  source: SocketAddr,
+  packet_size: usize,
+  received: SystemTime,
+  tag: Option<&ColoredString>,
+) {
+  let received_log_prefix = "\n📥 [RECEIVED]: ".blue().dimmed();
+  print_packet_table(
+    received_log_prefix,
+    packet,
+    source,
+    packet_size,
+    received,
+    tag,
+  );
+}
+
+/// Prints a packet the target sent back to us, eg. SuperCollider's `/done`.
+pub fn print_reply(packet: &osc::Packet, source: SocketAddr, packet_size: usize) {
+  let reply_log_prefix = "\n📨 [REPLY]: ".magenta().dimmed();
+  print_packet_table(
+    reply_log_prefix,
+    packet,
+    source,
+    packet_size,
+    SystemTime::now(),
+    None,
+  );
+}
+
+fn print_packet_table(
+  log_prefix: ColoredString,
+  packet: &osc::Packet,
+  source: SocketAddr,
+  packet_size: usize,
+  received: SystemTime,
+  tag: Option<&ColoredString>,
+) {
+  let x = termion::terminal_size().map(|(x, _)| x).unwrap_or(80);
+  let data = render::rows(
+    packet,
+    source.to_string(),
+    format!("{} bytes", packet_size),
+    received,
+  );
+  let mut table = Table::new(data);
+  table.with(THEME);
+  table.modify(Columns::last(), Width::wrap((x / 2) as usize));
+  print_log_prefix(log_prefix, tag);
+  println!("{table}\n");
+}
+
+// Prints what comes before a packet table, followed by the tag of the port it arrived on.
+fn print_log_prefix(log_prefix: ColoredString, tag: Option<&ColoredString>) {
+  match tag {
+    Some(tag) => println!("{}{}", log_prefix, tag),
+    None => println!("{}", log_prefix),
+  }
+}
+
+/// What to replay from a session file, where to, and how.
+pub struct ReplayOptions {
+  pub session: PathBuf,
+  pub host: String,
+  pub port: u16,
+  /// Playback speed multiplier, `2.0` plays twice as fast.
+  pub speed: f64,
+  /// Starts over once the last datagram has been sent.
+  pub repeat: bool,
+  /// Offsets into the session (from its first datagram) delimiting what is replayed.
+  pub start: Option<Duration>,
+  pub end: Option<Duration>,
+  /// Which datagrams, and within them which messages, are replayed.
+  pub filter: Filter,
+  pub rewrites: Vec<Rewrite>,
+}
+
+/// Re-sends the datagrams of a recorded session to `host:port`, preserving the time between them.
+///
+/// Datagrams are sent byte for byte as they were recorded, unless they have to be filtered or
+/// rewritten, in which case they are decoded and encoded again and the ones that cannot be decoded
+/// are skipped.
+pub fn replay(options: ReplayOptions) -> Result<(), Failure> {
+  let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+  let replayed_log_prefix = "📤 [REPLAYED]: ".blue().dimmed();
+  let records = match session::read(&options.session) {
+    Ok(records) => records,
+    Err(e) => {
+      println!("{}{}", failed_log_prefix, e.white().dimmed());
+      return Err(Failure::Io);
+    }
+  };
+  let full_address = host_port(&options.host, options.port);
+  let target = (options.host.as_str(), options.port);
+  let sender =
+    match osc::Connection::open(target, osc::Transport::Udp, &osc::SendOptions::default()) {
+      Ok(sender) => sender,
+      Err(e) => {
+        println!(
+          "{}{}",
+          failed_log_prefix,
+          format!("could not connect to {}, {}", full_address, e)
+            .white()
+            .dimmed()
+        );
+        return Err(Failure::Io);
+      }
+    };
+
+  let first = records.first().map(|r| r.timestamp).unwrap_or(0);
+  let start = options.start.unwrap_or_default();
+  let selected = records
+    .iter()
+    .map(|r| (Duration::from_micros(r.timestamp.saturating_sub(first)), r))
+    .filter(|(offset, _)| *offset >= start && options.end.iter().all(|end| *offset < *end))
+    .collect::<Vec<(Duration, &Record)>>();
+  println!(
+    "{}",
+    format!(
+      "replaying {} of {} datagrams from {} to {} at {}x",
+      selected.len(),
+      records.len(),
+      options.session.display(),
+      full_address,
+      options.speed
+    )
+    .dimmed()
+  );
+  if selected.is_empty() {
+    return Ok(());
+  }
+
+  let untouched = options.filter.is_empty() && options.rewrites.is_empty();
+  loop {
+    let started = Instant::now();
+    for (offset, record) in selected.iter() {
+      let due = (*offset - start).div_f64(options.speed);
+      if let Some(wait) = due.checked_sub(started.elapsed()) {
+        thread::sleep(wait);
+      }
+      let bytes = if untouched {
+        record.bytes.clone()
+      } else {
+        let packet = match osc::decode(&record.bytes) {
+          Ok(packet) => packet,
+          Err(_) => continue,
+        };
+        let packet = match options.filter.apply(packet, record.source) {
+          Some(packet) => rewrite_packet(packet, &options.rewrites),
+          None => continue,
+        };
+        match osc::encode(packet) {
+          Ok(bytes) => bytes,
+          Err(e) => {
+            println!(
+              "{}{}",
+              failed_log_prefix,
+              format!("could not encode, {:?}", e).white().dimmed()
+            );
+            continue;
+          }
+        }
+      };
+      match sender.send_bytes(&bytes) {
+        Ok(size) => println!(
+          "{}{}",
+          replayed_log_prefix,
+          format!(
+            "+{:.3}s {} ({} bytes)",
+            offset.as_secs_f64(),
+            summary(&bytes),
+            size
+          )
+          .white()
+          .dimmed()
+        ),
+        Err(e) => println!(
+          "{}{}",
+          failed_log_prefix,
+          format!("could not send, {}", e).white().dimmed()
+        ),
+      }
+    }
+    if !options.repeat {
+      return Ok(());
+    }
+  }
+}
+
+// A one line description of a datagram, eg. `/s_new "default" -1`.
+fn summary(bytes: &[u8]) -> String {
+  match osc::decode(bytes) {
+    Ok(packet) => describe(&packet),
+    Err(_) => "malformed datagram".to_string(),
+  }
+}
+
+// A one line description of a packet, eg. `/s_new "default" -1`.
+fn describe(packet: &osc::Packet) -> String {
+  match packet {
+    osc::Packet::Message(msg) => format!("{} {}", msg.addr, render::literals(&msg.args))
+      .trim_end()
+      .to_string(),
+    osc::Packet::Bundle(bundle) => format!(
+      "#bundle {} ({} elements)",
+      render::literal(&osc::Type::Time(bundle.timetag)),
+      bundle.content.len()
+    ),
+  }
+}
+
+/// Where the proxy listens, where it forwards to, and what happens to packets on the way.
+pub struct ProxyOptions {
+  pub bind: String,
+  pub port: u16,
+  /// Destinations, as `host:port`, of the packets no route applies to.
+  pub to: Vec<String>,
+  pub routes: Vec<Route>,
+  /// Rule file transforming the messages that are forwarded.
+  pub rules: Option<PathBuf>,
+  pub faults: Faults,
+}
+
+// A packet held back by the proxy, sent by calling the closure once it is due.
+type Delayed = (Instant, Box<dyn FnOnce() + Send>);
+
+/// Sits between a controller and its targets: forwards every packet received on `port` to its
+/// destinations, and every reply of a destination back to the sender of the packet, showing
+/// what passes through in both directions.
+///
+/// Each sender gets a socket of its own towards every destination, so that replies find their
+/// way back to the right sender. Packets are forwarded byte for byte, unless a rule of the rule
+/// file transforms one of their messages. Replies are never transformed.
+pub fn proxy(options: ProxyOptions) -> Result<(), Failure> {
+  let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+  let ProxyOptions {
+    bind,
+    port,
+    to,
+    routes,
+    rules,
+    faults,
+  } = options;
+  let rules = match rules {
+    Some(path) => match Rules::load(&path) {
+      Ok(rules) => {
+        println!(
+          "{}",
+          format!("loaded {} rules from {}", rules.len(), path.display()).dimmed()
+        );
+        rules
+      }
+      Err(e) => {
+        println!("{}{}", failed_log_prefix, e.white().dimmed());
+        return Err(Failure::Io);
+      }
+    },
+    None => Rules::default(),
+  };
+  let mut resolved = HashMap::new();
+  for dest in to.iter().chain(routes.iter().map(|route| &route.to)) {
+    match dest.to_socket_addrs().map(|mut addrs| addrs.next()) {
+      Ok(Some(addr)) => {
+        resolved.insert(dest.clone(), addr);
+      }
+      Ok(None) => {
+        println!(
+          "{}{}",
+          failed_log_prefix,
+          format!("could not resolve {}", dest).white().dimmed()
+        );
+        return Err(Failure::Io);
+      }
+      Err(e) => {
+        println!(
+          "{}{}",
+          failed_log_prefix,
+          format!("could not resolve {}, {}", dest, e)
+            .white()
+            .dimmed()
+        );
+        return Err(Failure::Io);
+      }
+    }
+  }
+  let listener =
+    match osc::Receiver::bind_to_with_mtu((bind.as_str(), port), osc::recv::MAX_UDP_PAYLOAD) {
+      Ok(listener) => Arc::new(listener),
+      Err(e) => {
+        println!(
+          "{}{}",
+          failed_log_prefix,
+          format!("could not listen on {}, {}", host_port(&bind, port), e)
+            .white()
+            .dimmed()
+        );
+        return Err(Failure::Io);
+      }
+    };
+  println!(
+    "{}",
+    format!(
+      "forwarding what is sent to {} to {}",
+      host_port(&bind, port),
+      match to.is_empty() {
+        true => "routed destinations only".to_string(),
+        false => to.join(", "),
+      }
+    )
+    .dimmed()
+  );
+  let mut simulated = vec![];
+  if faults.drop > 0.0 {
+    simulated.push(format!("dropping {}%", faults.drop * 100.0));
+  }
+  if faults.duplicate > 0.0 {
+    simulated.push(format!("duplicating {}%", faults.duplicate * 100.0));
+  }
+  if !faults.delay.is_zero() {
+    simulated.push(format!("delaying by {} ms", faults.delay.as_millis()));
+  }
+  if !simulated.is_empty() {
+    println!(
+      "{}",
+      format!("{} of the packets, both ways", simulated.join(", ")).dimmed()
+    );
+  }
+
+  let delay_line = delay_line();
+  let sessions: Sessions = Arc::new(Mutex::new(HashMap::new()));
+  let mut random = Random::from_time();
+  loop {
+    let (bytes, client) = match listener.recv_bytes() {
+      Ok(received) => received,
+      Err(e) => {
+        println!(
+          "{}{}",
+          failed_log_prefix,
+          format!("could not receive, {}", e).white().dimmed()
+        );
+        if is_fatal(&e) {
+          return Err(Failure::Io);
+        }
+        continue;
+      }
+    };
+    let packet = osc::decode(&bytes).ok();
+    let dests = proxy::destinations(packet.as_ref(), &routes, &to);
+    let copies = faults.copies(&mut random);
+    let route = format!(
+      "{} → {}",
+      osc::unmapped(client),
+      dests
+        .iter()
+        .map(|dest| dest.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ")
+    );
+    print_passing(&bytes, client, route, copies, faults.delay, false);
+    if copies == 0 {
+      continue;
+    }
+    let bytes = match packet {
+      Some(packet) if !rules.is_empty() => transform(bytes, packet, &rules, client),
+      _ => bytes,
+    };
+    let bytes = Arc::new(bytes);
+    for dest in dests {
+      let addr = resolved[dest];
+      let mut open = lock(&sessions);
+      let session = match open.get_mut(&(client, addr)) {
+        Some(session) => {
+          session.used = Instant::now();
+          Arc::clone(&session.connection)
+        }
+        None => {
+          let session =
+            match osc::Connection::open(addr, osc::Transport::Udp, &osc::SendOptions::default())
+              .and_then(|session| {
+                session
+                  .set_read_timeout(Some(SESSION_IDLE))
+                  .map(|_| session)
+              }) {
+              Ok(session) => Arc::new(session),
+              Err(e) => {
+                println!(
+                  "{}{}",
+                  failed_log_prefix,
+                  format!("could not connect to {}, {}", dest, e)
+                    .white()
+                    .dimmed()
+                );
+                continue;
+              }
+            };
+          open.insert(
+            (client, addr),
+            Session {
+              connection: Arc::clone(&session),
+              used: Instant::now(),
+            },
+          );
+          relay_replies(
+            Arc::clone(&session),
+            Arc::clone(&sessions),
+            Arc::clone(&listener),
+            client,
+            faults,
+            delay_line.clone(),
+          );
+          session
+        }
+      };
+      drop(open);
+      let bytes = Arc::clone(&bytes);
+      let dest = dest.clone();
+      pass_on(&delay_line, faults.delay, move || {
+        for _ in 0..copies {
+          if let Err(e) = session.send_bytes(&bytes) {
+            println!(
+              "{}{}",
+              "❌ [FAILED]: ".to_string().red().dimmed(),
+              format!("could not forward to {}, {}", dest, e)
+                .white()
+                .dimmed()
+            );
+          }
+        }
+      });
+    }
+  }
+}
+
+// Applies the rules to a packet about to be forwarded, returning the bytes to forward and
+// printing the packet if it changed.
+fn transform(bytes: Vec<u8>, packet: osc::Packet, rules: &Rules, source: SocketAddr) -> Vec<u8> {
+  let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+  let mut errors = vec![];
+  let transformed = rules.apply(packet.clone(), &mut errors);
+  for e in errors {
+    println!("{}{}", failed_log_prefix, e.white().dimmed());
+  }
+  if transformed == packet {
+    return bytes;
+  }
+  match osc::encode(transformed.clone()) {
+    Ok(encoded) => {
+      print_packet_table(
+        "\n✏️  [TRANSFORMED]: ".cyan().dimmed(),
+        &transformed,
+        osc::unmapped(source),
+        encoded.len(),
+        SystemTime::now(),
+        None,
+      );
+      encoded
+    }
+    Err(e) => {
+      println!(
+        "{}{}",
+        failed_log_prefix,
+        format!("could not encode, forwarding as received, {:?}", e)
+          .white()
+          .dimmed()
+      );
+      bytes
+    }
+  }
+}
+
+// How long a client and a destination may exchange nothing before their session is closed, along
+// with its socket and the thread relaying its replies.
+const SESSION_IDLE: Duration = Duration::from_secs(120);
+
+// The sessions of the proxy, by client and destination address.
+type Sessions = Arc<Mutex<HashMap<(SocketAddr, SocketAddr), Session>>>;
+
+// The socket a client's packets are forwarded from, so that replies can be told apart by client.
+struct Session {
+  connection: Arc<osc::Connection>,
+  // When a packet was last forwarded or replied, either way.
+  used: Instant,
+}
+
+// The sessions are only ever updated field by field, they are still consistent after a panic.
+fn lock(sessions: &Sessions) -> MutexGuard<'_, HashMap<(SocketAddr, SocketAddr), Session>> {
+  sessions.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Sends whatever the destination of `session` replies back to `client`, from the port the proxy
+// listens on, until the session is idle for `SESSION_IDLE` and closed.
+fn relay_replies(
+  session: Arc<osc::Connection>,
+  sessions: Sessions,
+  listener: Arc<osc::Receiver>,
+  client: SocketAddr,
+  faults: Faults,
+  delay_line: mpsc::Sender<Delayed>,
+) {
+  let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+  thread::spawn(move || {
+    let mut random = Random::from_time();
+    let dest = session.remote_addr();
+    let key = (client, dest);
+    loop {
+      let bytes = match session.recv_bytes() {
+        Ok(bytes) => bytes,
+        Err(osc::CommunicationError::Io(e))
+          if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut =>
+        {
+          let mut open = lock(&sessions);
+          match open.get(&key) {
+            Some(active) if active.used.elapsed() < SESSION_IDLE => continue,
+            _ => {
+              open.remove(&key);
+              break;
+            }
+          }
+        }
+        // An ICMP "port unreachable" from a previous forward, later replies still get through.
+        Err(osc::CommunicationError::Io(e)) if e.kind() == ErrorKind::ConnectionRefused => {
+          println!(
+            "{}{}",
+            failed_log_prefix,
+            format!("nothing is listening on {}", dest).white().dimmed()
+          );
+          continue;
+        }
+        Err(e) => {
+          println!(
+            "{}{}",
+            failed_log_prefix,
+            format!("stopped relaying replies from {}, {}", dest, e)
+              .white()
+              .dimmed()
+          );
+          // The next packet of the client opens a new session.
+          lock(&sessions).remove(&key);
+          break;
+        }
+      };
+      if let Some(replied) = lock(&sessions).get_mut(&key) {
+        replied.used = Instant::now();
+      }
+      let copies = faults.copies(&mut random);
+      let route = format!("{} → {}", dest, osc::unmapped(client));
+      print_passing(&bytes, dest, route, copies, faults.delay, true);
+      let listener = Arc::clone(&listener);
+      pass_on(&delay_line, faults.delay, move || {
+        for _ in 0..copies {
+          if let Err(e) = listener.send_bytes_to(&bytes, client) {
+            println!(
+              "{}{}",
+              "❌ [FAILED]: ".to_string().red().dimmed(),
+              format!("could not relay a reply to {}, {}", client, e)
+                .white()
+                .dimmed()
+            );
+          }
+        }
+      });
+    }
+  });
+}
+
+// Holds packets back until their delay is over. Every packet is delayed by the same amount, so
+// they are due in the order they arrive.
+fn delay_line() -> mpsc::Sender<Delayed> {
+  let (sender, delayed) = mpsc::channel::<Delayed>();
+  thread::spawn(move || {
+    for (due, send) in delayed {
+      if let Some(wait) = due.checked_duration_since(Instant::now()) {
+        thread::sleep(wait);
+      }
+      send();
+    }
+  });
+  sender
+}
+
+fn pass_on<F>(delay_line: &mpsc::Sender<Delayed>, delay: Duration, send: F)
+where
+  F: FnOnce() + Send + 'static,
+{
+  if delay.is_zero() {
+    send();
+  } else {
+    // Only fails once the delay line is gone, along with the proxy.
+    let _ = delay_line.send((Instant::now() + delay, Box::new(send)));
   }
 }
 
-pub fn send(port: u16, address: String) {
+// Prints a packet passing through the proxy, along with its route and what happens to it.
+fn print_passing(
+  bytes: &[u8],
+  source: SocketAddr,
+  route: String,
+  copies: usize,
+  delay: Duration,
+  reply: bool,
+) {
+  let log_prefix = match (copies, reply) {
+    (0, _) => "\n🚫 [DROPPED]: ".red().dimmed(),
+    (_, true) => "\n📨 [REPLY]: ".magenta().dimmed(),
+    (_, false) => "\n🔀 [FORWARDED]: ".blue().dimmed(),
+  };
+  let mut tag = route;
+  if copies == 2 {
+    tag.push_str(", twice");
+  }
+  if copies > 0 && !delay.is_zero() {
+    tag.push_str(&format!(", delayed by {} ms", delay.as_millis()));
+  }
+  match osc::decode(bytes) {
+    Ok(packet) => print_packet_table(
+      log_prefix,
+      &packet,
+      osc::unmapped(source),
+      bytes.len(),
+      SystemTime::now(),
+      Some(&tag.dimmed()),
+    ),
+    // Shown as malformed rather than with the prefix of its fate, which then goes in the tag.
+    Err(e) => {
+      if copies == 0 {
+        tag.push_str(", dropped");
+      }
+      print_malformed(bytes, osc::unmapped(source), e, Some(&tag.dimmed()))
+    }
+  }
+}
+
+/// Where `send`, `send_once` and `query` send to, and how.
+pub struct Target {
+  pub host: String,
+  pub port: u16,
+  pub transport: osc::Transport,
+  pub options: osc::SendOptions,
+}
+
+/// Opens the interactive editor sending every line typed to the target.
+///
+/// All messages go out of the same socket, whatever the target sends back to it is printed as it
+/// arrives. With `schedule`, bundles are held back and sent at the time of their timetag.
+pub fn send(target: Target, schedule: bool) -> Result<(), Failure> {
+  let sender = Arc::new(connect(&target)?);
+  let scheduler = match schedule {
+    true => Some(scheduler(&sender, Arc::new(AtomicBool::new(false)))),
+    false => None,
+  };
   let config = Config::builder()
     .history_ignore_space(true)
     .completion_type(CompletionType::List)
@@ -106,7 +1343,7 @@ pub fn send(port: u16, address: String) {
   let mut screen = AlternateScreen::from(stdout());
   println!( "{}",
     &format!( "\x1b[38;5;242m{} \x1b[38;5;242m{} \x1b[38;5;242m{} \x1b[38;5;242m{} \x1b[38;5;242m{} \x1b[38;5;242m{} \x1b[38;5;242m{} \x1b[38;5;242m{} \x1b[38;5;242m{}\x1b[38;5;242m{}",
-      format!("Sending OSC messages to {:?}: {:?} \n",address, port).bold(),
+      format!("Sending OSC messages to {:?}: {:?} \n",target.host, target.port).bold(),
       "Use the following format to send messages: <address> <value>\n",
       "- <address> is osc path to communicate with\n",
       "- <value> is a number or a string without wrapping in double quotes (can have multiple values) \n",
@@ -118,6 +1355,7 @@ pub fn send(port: u16, address: String) {
   );
   screen.flush().unwrap();
 
+  listen_for_replies(Arc::clone(&sender));
   let handler = thread::spawn(move || loop {
     let p = "> ".to_string();
     rl.helper_mut().expect("No helper").colored_prompt = format!("\x1b[1;32m{p}\x1b[0m");
@@ -129,66 +1367,11 @@ pub fn send(port: u16, address: String) {
         break;
       }
       Ok(input) => {
-        let (osc_msg_vec, lex_error) = Lexer::analyse(&input);
-        let tokens = Tokens::new(&osc_msg_vec);
-        let vec = Vec::new();
-        let (_, stmt) = Parser::parse_tokens(tokens).unwrap_or((Tokens::new(&vec), Vec::new()));
-        let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
-        match (stmt.split_first(), lex_error.is_empty()) {
-          (None, true) => {
-            let data = vec![TableError::new(
-              "-".to_string(),
-              "-".to_string(),
-              r#"invalid argument or empty msg, please check argument again or try: /s_new "default" -1 0 0 "freq" 850"#.to_string(),
-              "-".to_string(),
-            )];
-            let mut table = Table::new(data);
-            table.with(THEME);
-
-            println!("\n{}", failed_log_prefix,);
-            println!("{table}\n");
-          }
-          (Some((first, tail)), true) => {
-            match first {
-              Stmt::ExprStmt(Expr::Lit(Literal::OscPath(osc_path))) => {
-                match tail.first().unwrap()  {
-                  Stmt::ExprStmt(Expr::Lit(Literal::OscPath(invalid_msg)))  => println!(
-                    "{}{}",
-                    failed_log_prefix,
-                    format!(r#"OSC path is already declared. If you intended to send msg as string, try wrapping {} in double quotes, example: "this is a string""#, invalid_msg).white().dimmed()
-                  ),
-                  _ => {
-                    let argument_msg = tail
-                    .iter()
-                    .map(|x| match x {
-                      Stmt::ExprStmt(v) => parse_message(v),
-                    })
-                    .collect::<Vec<OscType>>();
-                  send_packet(port, address.clone(), osc_path, argument_msg);
-                  }
-                }
-                
-              }
-              _ => println!(
-                "{}{}",
-                failed_log_prefix,
-                "osc path should start with / eg. /s_new".white().dimmed()
-              ),
-            };
-          }
-          (_, _) => {
-            let mut data = vec![];
-            for err in lex_error {
-              let errors = err.print_error();
-              data.push(TableError::new(errors.0, errors.1, errors.2, errors.3));
-            }
-
-            let mut table = Table::new(data);
-            table.with(THEME);
-
-            println!("\n{}", failed_log_prefix,);
-            println!("{table}\n");
-          }
+        if let Ok(packet) = analyse_input(&input) {
+          let _ = match &scheduler {
+            Some(scheduler) => schedule_packet(scheduler, &sender, packet),
+            None => send_packet(&sender, packet).map(|_| ()),
+          };
         }
 
         rl.add_history_entry(input.as_str()).unwrap();
@@ -197,37 +1380,917 @@ pub fn send(port: u16, address: String) {
   });
 
   handler.join().unwrap();
+  Ok(())
 }
 
-pub fn send_packet(port: u16, address: String, osc_path: &str, osc_args: Vec<OscType>) {
-  let full_address = format!("{}:{}", address, port);
-  let (x, _) = termion::terminal_size().unwrap();
-  let success_log_prefix = "\n✅ [SUCCESS]: ".green().dimmed();
+// Prints the replies received on the sender's socket from a background thread, for as long as
+// the socket can be read from.
+fn listen_for_replies(sender: Arc<osc::Connection>) {
   let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+  thread::spawn(move || loop {
+    let source = sender.remote_addr();
+    match sender.recv_bytes() {
+      Ok(bytes) => match osc::decode(&bytes) {
+        Ok(packet) => print_reply(&packet, source, bytes.len()),
+        Err(e) => print_malformed(&bytes, source, e, None),
+      },
+      // An ICMP "port unreachable" from a previous send surfaces here, it does not prevent
+      // later replies.
+      Err(osc::CommunicationError::Io(e)) if e.kind() == ErrorKind::ConnectionRefused => println!(
+        "{}{}",
+        failed_log_prefix,
+        format!("nothing is listening on {}", source)
+          .white()
+          .dimmed()
+      ),
+      // Over TCP, the next send opens the connection again.
+      Err(osc::CommunicationError::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => println!(
+        "{}{}",
+        failed_log_prefix,
+        format!("{}, reconnecting on the next send", e)
+          .white()
+          .dimmed()
+      ),
+      Err(osc::CommunicationError::Io(e)) if e.kind() == ErrorKind::NotConnected => {
+        thread::sleep(Duration::from_millis(100))
+      }
+      Err(e) => {
+        println!(
+          "{}{}",
+          failed_log_prefix,
+          format!("stopped listening for replies, {}", e)
+            .white()
+            .dimmed()
+        );
+        break;
+      }
+    }
+  });
+}
 
-  let sender = osc::sender()
-    .expect("Could not bind to default socket")
-    .connect(full_address)
-    .expect("Could not connect to socket at address");
+/// Sends a single line of input to the target without entering the interactive editor.
+///
+/// With `schedule`, a bundle is held back until the time of its timetag before exiting.
+pub fn send_once(
+  target: Target,
+  input: &str,
+  schedule: bool,
+  syntax: Syntax,
+) -> Result<(), Failure> {
+  let packet = match syntax {
+    Syntax::Literal => analyse_input(input)?,
+    Syntax::Json => analyse_json(input)?,
+  };
+  let sender = connect(&target)?;
+  if !schedule {
+    return send_packet(&sender, packet).map(|_| ());
+  }
+  let sender = Arc::new(sender);
+  let failed = Arc::new(AtomicBool::new(false));
+  let scheduler = scheduler(&sender, Arc::clone(&failed));
+  schedule_packet(&scheduler, &sender, packet)?;
+  scheduler.finish();
+  match failed.load(AtomicOrdering::SeqCst) {
+    true => Err(Failure::Io),
+    false => Ok(()),
+  }
+}
 
-  let packet = (osc_path, osc_args);
-  match sender.send(packet.clone()) {
-    Ok(value) => {
-      let data = vec![TableSuccess::new(
-        format!("{} bytes", value),
-        packet.0.to_string(),
-        format!("{:?}", packet.1),
+/// Sends a single line of input to the target and waits up to `timeout` for a reply whose
+/// address matches `expect` (or for any reply when there is none), printing it along with the
+/// round-trip time.
+///
+/// The reply is read from the socket the message was sent from, which is where servers such as
+/// scsynth answer.
+pub fn query(
+  target: Target,
+  input: &str,
+  expect: Option<Pattern>,
+  timeout: Duration,
+) -> Result<(), Failure> {
+  let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+  let packet = analyse_input(input)?;
+  let sender = connect(&target)?;
+  let sent = Instant::now();
+  send_packet(&sender, packet)?;
+
+  let source = sender.remote_addr();
+  let expected = match &expect {
+    Some(pattern) => format!("reply matching {}", pattern),
+    None => "reply".to_string(),
+  };
+  loop {
+    let remaining = match timeout.checked_sub(sent.elapsed()) {
+      Some(remaining) if !remaining.is_zero() => remaining,
+      _ => break,
+    };
+    if let Err(e) = sender.set_read_timeout(Some(remaining)) {
+      println!("{}{}", failed_log_prefix, format!("{}", e).white().dimmed());
+      return Err(Failure::Io);
+    }
+    match sender.recv_bytes() {
+      Ok(bytes) => {
+        let packet = match osc::decode(&bytes) {
+          Ok(packet) => packet,
+          Err(e) => {
+            print_malformed(&bytes, source, e, None);
+            continue;
+          }
+        };
+        let rtt = sent.elapsed();
+        if is_expected(&packet, expect.as_ref()) {
+          print_reply(&packet, source, bytes.len());
+          println!(
+            "{}",
+            format!("round trip: {:.3} ms\n", rtt.as_secs_f64() * 1000.0).dimmed()
+          );
+          return Ok(());
+        }
+      }
+      Err(osc::CommunicationError::Io(e))
+        if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut =>
+      {
+        break
+      }
+      Err(osc::CommunicationError::Io(e)) if e.kind() == ErrorKind::ConnectionRefused => {
+        println!(
+          "{}{}",
+          failed_log_prefix,
+          format!("nothing is listening on {}", source)
+            .white()
+            .dimmed()
+        );
+        return Err(Failure::Io);
+      }
+      Err(e) => {
+        println!(
+          "{}{}",
+          failed_log_prefix,
+          format!("could not receive, {}", e).white().dimmed()
+        );
+        return Err(Failure::Io);
+      }
+    }
+  }
+  println!(
+    "{}{}",
+    failed_log_prefix,
+    format!(
+      "timed out, no {} from {} within {} ms",
+      expected,
+      source,
+      timeout.as_millis()
+    )
+    .white()
+    .dimmed()
+  );
+  Err(Failure::Timeout)
+}
+
+// Whether `packet` is the reply `query` waits for, any packet when no pattern is expected,
+// otherwise one holding a message whose address matches it, eg. within a bundle.
+fn is_expected(packet: &osc::Packet, expect: Option<&Pattern>) -> bool {
+  match expect {
+    Some(pattern) => packet
+      .clone()
+      .into_msgs()
+      .iter()
+      .any(|msg| pattern.matches(&msg.addr)),
+    None => true,
+  }
+}
+
+/// Sends the lines of a script file to the target one after the other, pausing at `wait` lines,
+/// and reports how each of them went.
+///
+/// The whole script is checked before anything is sent: every line that cannot be lexed or
+/// parsed is reported along with its position, eg. `show.osc:3:9: ...`, and nothing is sent.
+pub fn run(script: PathBuf, target: Target, syntax: Syntax) -> Result<(), Failure> {
+  let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+  let success_log_prefix = "✅ [SUCCESS]: ".green().dimmed();
+  let name = script.display().to_string();
+  let content = match fs::read_to_string(&script) {
+    Ok(content) => content,
+    Err(e) => {
+      println!(
+        "{}{}",
+        failed_log_prefix,
+        format!("{}: {}", name, e).white().dimmed()
+      );
+      return Err(Failure::Io);
+    }
+  };
+
+  let mut steps = vec![];
+  let mut failure = None;
+  for (i, line) in content.lines().enumerate() {
+    let number = i + 1;
+    let step = match syntax {
+      Syntax::Literal => Step::parse(line),
+      // JSON scripts are made of packets only.
+      Syntax::Json => Ok((!line.trim().is_empty()).then(|| Step::Send(line.to_string()))),
+    };
+    match step {
+      Ok(None) => (),
+      Ok(Some(Step::Wait(duration))) => steps.push((number, Step::Wait(duration))),
+      // Checked up front, but parsed again right before being sent, so that relative timetags
+      // are counted from the time the packet is sent rather than from the time the script is
+      // loaded.
+      Ok(Some(Step::Send(input))) => match parse_line(&name, number, &input, syntax) {
+        Ok(_) => steps.push((number, Step::Send(input))),
+        Err(e) => {
+          failure.get_or_insert(e);
+        }
+      },
+      Err((column, e)) => {
+        print_diagnostic(&name, number, column, &e);
+        failure.get_or_insert(Failure::Parse);
+      }
+    }
+  }
+  if let Some(failure) = failure {
+    return Err(failure);
+  }
+
+  let sender = connect(&target)?;
+  let total = steps
+    .iter()
+    .filter(|(_, step)| matches!(step, Step::Send(_)))
+    .count();
+  println!(
+    "{}",
+    format!(
+      "sending {} packets from {} to {}",
+      total,
+      name,
+      host_port(&target.host, target.port)
+    )
+    .dimmed()
+  );
+  let mut sent = 0;
+  for (number, step) in steps {
+    let packet = match step {
+      Step::Wait(duration) => {
+        thread::sleep(duration);
+        continue;
+      }
+      Step::Send(input) => match parse_line(&name, number, &input, syntax) {
+        Ok(packet) => packet,
+        Err(_) => continue,
+      },
+    };
+    match sender.send(packet.clone()) {
+      Ok(size) => {
+        sent += 1;
+        println!(
+          "{}{}",
+          success_log_prefix,
+          format!("{}:{}: {}, {} bytes", name, number, describe(&packet), size)
+            .white()
+            .dimmed()
+        );
+      }
+      Err(e) => println!(
+        "{}{}",
+        failed_log_prefix,
+        format!("{}:{}: could not send, {}", name, number, e)
+          .white()
+          .dimmed()
+      ),
+    }
+  }
+  println!("{}", format!("sent {} of {} packets", sent, total).dimmed());
+  match sent == total {
+    true => Ok(()),
+    false => Err(Failure::Io),
+  }
+}
+
+/// Sends every line read from stdin to the target as it arrives, eg. at the end of a shell
+/// pipeline, until stdin is closed. Blank lines are skipped.
+///
+/// Lines that cannot be lexed or parsed are reported along with their position, eg.
+/// `stdin:3:9: ...`. Unless `keep_going` is set the first of them stops the pipeline, otherwise
+/// the failure of the first one is returned once stdin is closed.
+pub fn send_stdin(
+  target: Target,
+  schedule: bool,
+  keep_going: bool,
+  syntax: Syntax,
+) -> Result<(), Failure> {
+  let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+  let success_log_prefix = "✅ [SUCCESS]: ".green().dimmed();
+  let sender = Arc::new(connect(&target)?);
+  let failed = Arc::new(AtomicBool::new(false));
+  let scheduler = match schedule {
+    true => Some(scheduler(&sender, Arc::clone(&failed))),
+    false => None,
+  };
+  listen_for_replies(Arc::clone(&sender));
+
+  let read = send_lines(stdin().lock(), keep_going, |number, line| {
+    parse_line("stdin", number, line, syntax).and_then(|packet| match &scheduler {
+      Some(scheduler) if is_scheduled(&packet) => schedule_packet(scheduler, &sender, packet),
+      _ => match sender.send(packet.clone()) {
+        Ok(size) => {
+          println!(
+            "{}{}",
+            success_log_prefix,
+            format!("stdin:{}: {}, {} bytes", number, describe(&packet), size)
+              .white()
+              .dimmed()
+          );
+          Ok(())
+        }
+        Err(e) => {
+          println!(
+            "{}{}",
+            failed_log_prefix,
+            format!("stdin:{}: could not send, {}", number, e)
+              .white()
+              .dimmed()
+          );
+          Err(Failure::Io)
+        }
+      },
+    })
+  });
+  let mut failure = read.unwrap_or_else(|e| {
+    println!(
+      "{}{}",
+      failed_log_prefix,
+      format!("could not read stdin, {}", e).white().dimmed()
+    );
+    Some(Failure::Io)
+  });
+  if let Some(scheduler) = scheduler {
+    scheduler.finish();
+    if failed.load(AtomicOrdering::SeqCst) {
+      failure.get_or_insert(Failure::Io);
+    }
+  }
+  match failure {
+    Some(failure) => Err(failure),
+    None => Ok(()),
+  }
+}
+
+// Hands every line of `reader` that is not blank to `send`, along with its number, and returns
+// the first failure. Stops at the first line that cannot be sent unless `keep_going`, and fails as
+// soon as `reader` cannot be read.
+fn send_lines<R, F>(
+  reader: R,
+  keep_going: bool,
+  mut send: F,
+) -> Result<Option<Failure>, std::io::Error>
+where
+  R: BufRead,
+  F: FnMut(usize, &str) -> Result<(), Failure>,
+{
+  let mut failure = None;
+  for (i, line) in reader.lines().enumerate() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    if let Err(e) = send(i + 1, &line) {
+      failure.get_or_insert(e);
+      if !keep_going {
+        break;
+      }
+    }
+  }
+  Ok(failure)
+}
+
+// Lexes and parses a line read from `name`, printing what is wrong with it along with its
+// position, eg. `show.osc:3:9: ...`.
+fn parse_line(
+  name: &str,
+  number: usize,
+  line: &str,
+  syntax: Syntax,
+) -> Result<osc::Packet, Failure> {
+  let start = script::column(line, line.len() - line.trim_start().len());
+  let parsed = match syntax {
+    Syntax::Literal => parse_input(line),
+    Syntax::Json => {
+      return json::parse(line).map_err(|(column, e)| {
+        print_diagnostic(name, number, column.unwrap_or(start), &e);
+        Failure::Parse
+      })
+    }
+  };
+  match parsed {
+    Ok(packet) => Ok(packet),
+    Err(Invalid::Lex(errors)) => {
+      for e in errors {
+        let column = script::column(line, e.range().start);
+        let message = format!("{} `{}`", e.message(), e.input());
+        print_diagnostic(name, number, column, &message);
+      }
+      Err(Failure::Lex)
+    }
+    Err(Invalid::Empty) => {
+      print_diagnostic(name, number, start, EMPTY_INPUT);
+      Err(Failure::Parse)
+    }
+    Err(Invalid::Parse(e)) => {
+      print_diagnostic(name, number, start, &e);
+      Err(Failure::Parse)
+    }
+  }
+}
+
+fn print_diagnostic(name: &str, number: usize, column: usize, e: &str) {
+  println!(
+    "{}{}",
+    "❌ [FAILED]: ".to_string().red().dimmed(),
+    format!("{}:{}:{}: {}", name, number, column, e)
+      .white()
+      .dimmed()
+  )
+}
+
+/// How the packets to send are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Syntax {
+  /// `oscd`'s own literals, eg. `/s_new "default" -1 0 0`.
+  #[default]
+  Literal,
+  /// JSON objects, eg. `{"address":"/status"}`, see `json`.
+  Json,
+}
+
+/// Why a line of input could not be turned into a packet.
+pub enum Invalid {
+  /// The input could not be lexed, along with every problem the lexer found.
+  Lex(Vec<LexError>),
+  /// The input holds no statement at all.
+  Empty,
+  /// The tokens did not form a valid message or bundle.
+  Parse(String),
+}
+
+/// Lexes and parses a line of input into an OSC packet, a single message or a bundle, without
+/// printing anything.
+pub fn parse_input(input: &str) -> Result<osc::Packet, Invalid> {
+  let (osc_msg_vec, lex_error) = Lexer::analyse(input);
+  let tokens = Tokens::new(&osc_msg_vec);
+  let vec = Vec::new();
+  let (_, stmt) = Parser::parse_tokens(tokens).unwrap_or((Tokens::new(&vec), Vec::new()));
+  match (stmt.is_empty(), lex_error.is_empty()) {
+    (true, true) => Err(Invalid::Empty),
+    (false, true) => analyse_stmts(&stmt).map_err(Invalid::Parse),
+    (_, _) => Err(Invalid::Lex(lex_error)),
+  }
+}
+
+/// Lexes and parses a line of input into an OSC packet, a single message or a bundle.
+///
+/// Every problem found along the way is printed, the returned `Failure` tells at which stage the
+/// input was rejected.
+pub fn analyse_input(input: &str) -> Result<osc::Packet, Failure> {
+  let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+  match parse_input(input) {
+    Ok(packet) => Ok(packet),
+    Err(Invalid::Empty) => {
+      let data = vec![TableError::new(
+        "-".to_string(),
+        "-".to_string(),
+        EMPTY_INPUT.to_string(),
+        "-".to_string(),
       )];
       let mut table = Table::new(data);
       table.with(THEME);
-      table.modify(Columns::last(), Width::wrap((x / 2) as usize));
-      println!("{}", success_log_prefix);
+
+      println!("\n{}", failed_log_prefix,);
       println!("{table}\n");
+      Err(Failure::Parse)
     }
-    Err(e) => println!(
+    Err(Invalid::Parse(e)) => {
+      println!("{}{}", failed_log_prefix, e.white().dimmed());
+      Err(Failure::Parse)
+    }
+    Err(Invalid::Lex(lex_error)) => {
+      let mut data = vec![];
+      for err in lex_error {
+        let errors = err.print_error();
+        data.push(TableError::new(errors.0, errors.1, errors.2, errors.3));
+      }
+
+      let mut table = Table::new(data);
+      table.with(THEME);
+
+      println!("\n{}", failed_log_prefix,);
+      println!("{table}\n");
+      Err(Failure::Lex)
+    }
+  }
+}
+
+/// Reads a packet written as JSON, printing what is wrong with it if anything.
+pub fn analyse_json(input: &str) -> Result<osc::Packet, Failure> {
+  json::parse(input).map_err(|(column, e)| {
+    let e = match column {
+      Some(column) => format!("{} at column {}", e, column),
+      None => e,
+    };
+    println!(
       "{}{}",
-      failed_log_prefix,
-      format!("{:?}", e).white().dimmed()
+      "❌ [FAILED]: ".to_string().red().dimmed(),
+      e.white().dimmed()
+    );
+    Failure::Parse
+  })
+}
+
+const EMPTY_INPUT: &str = r#"invalid argument or empty msg, please check argument again or try: /s_new "default" -1 0 0 "freq" 850"#;
+
+// Turns the statements of a message (an OSC path followed by its arguments) or of a single
+// bundle into a packet, recursing into the elements of bundles.
+fn analyse_stmts(stmts: &[Stmt]) -> Result<osc::Packet, String> {
+  match stmts.split_first() {
+    Some((Stmt::BundleStmt(timetag, elements), [])) => {
+      let content = elements
+        .iter()
+        .map(|element| analyse_stmts(element).map(OscPacket::from))
+        .collect::<Result<Vec<OscPacket>, String>>()?;
+      Ok(osc::Packet::Bundle(osc::Bundle {
+        timetag: parse_bundle_timetag(timetag),
+        content,
+      }))
+    }
+    Some((Stmt::ExprStmt(Expr::Lit(Literal::OscPath(osc_path))), tail)) => match tail.first() {
+      Some(Stmt::ExprStmt(Expr::Lit(Literal::OscPath(invalid_msg)))) => Err(format!(
+        r#"OSC path is already declared. If you intended to send msg as string, try wrapping {} in double quotes, example: "this is a string""#,
+        invalid_msg
+      )),
+      _ => {
+        let mut argument_msg = vec![];
+        for x in tail {
+          match x {
+            Stmt::ExprStmt(v) => argument_msg.push(parse_message(v)),
+            Stmt::BundleStmt(..) => {
+              return Err(format!("a bundle cannot be an argument of {}, separate bundle elements with `;` eg. #bundle {{ /a 1; /b 2 }}", osc_path));
+            }
+          }
+        }
+        Ok(osc::Packet::Message(osc::msg(
+          osc_path.clone(),
+          argument_msg,
+        )))
+      }
+    },
+    Some((Stmt::BundleStmt(..), _)) => Err(
+      "a bundle cannot be followed by arguments, put them in a message inside the bundle"
+        .to_string(),
     ),
+    _ => Err("osc path should start with / eg. /s_new".to_string()),
+  }
+}
+
+// Joins a host and a port the way socket addresses are written, with IPv6 addresses between
+// brackets.
+fn host_port(host: &str, port: u16) -> String {
+  match host.contains(':') {
+    true => format!("[{}]:{}", host, port),
+    false => format!("{}:{}", host, port),
+  }
+}
+
+/// Connects to the target, from a free local port for UDP.
+pub fn connect(target: &Target) -> Result<osc::Connection, Failure> {
+  let addr = (target.host.as_str(), target.port);
+  match osc::Connection::open(addr, target.transport, &target.options) {
+    Ok(sender) => Ok(sender),
+    Err(e) => {
+      println!(
+        "{}{}",
+        "❌ [FAILED]: ".to_string().red().dimmed(),
+        format!("{:?}", e).white().dimmed()
+      );
+      Err(Failure::Io)
+    }
+  }
+}
+
+pub fn send_packet(sender: &osc::Connection, packet: osc::Packet) -> Result<usize, Failure> {
+  let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+
+  match sender.send(packet.clone()) {
+    Ok(value) => {
+      print_sent(&packet, value);
+      Ok(value)
+    }
+    Err(e) => {
+      println!(
+        "{}{}",
+        failed_log_prefix,
+        format!("{:?}", e).white().dimmed()
+      );
+      Err(Failure::Io)
+    }
+  }
+}
+
+fn print_sent(packet: &osc::Packet, size: usize) {
+  let x = termion::terminal_size().map(|(x, _)| x).unwrap_or(80);
+  let success_log_prefix = "\n✅ [SUCCESS]: ".green().dimmed();
+  let data = render::sent_rows(packet, format!("{} bytes", size));
+  let mut table = Table::new(data);
+  table.with(THEME);
+  table.modify(Columns::last(), Width::wrap((x / 2) as usize));
+  println!("{}", success_log_prefix);
+  println!("{table}\n");
+}
+
+/// Starts a scheduler sending through `sender` that prints every packet it sends along with how
+/// late it went out compared to its timetag. `failed` is set when a packet could not be sent.
+pub fn scheduler(sender: &Arc<osc::Connection>, failed: Arc<AtomicBool>) -> osc::Scheduler {
+  let failed_log_prefix = "❌ [FAILED]: ".to_string().red().dimmed();
+  let mut jitter = Jitter::default();
+  osc::Scheduler::new(Arc::clone(sender), move |dispatch| match dispatch.result {
+    Ok(size) => {
+      print_sent(&dispatch.packet, size);
+      jitter.record(dispatch.jitter);
+      println!("{}", format!("⏱  {}\n", jitter).dimmed());
+    }
+    Err(e) => {
+      failed.store(true, AtomicOrdering::SeqCst);
+      println!(
+        "{}{}",
+        failed_log_prefix,
+        format!("{:?}", e).white().dimmed()
+      );
+    }
+  })
+}
+
+/// Sends `packet` through `scheduler` at the time of its timetag if it is a bundle, or right away
+/// through `sender` otherwise.
+pub fn schedule_packet(
+  scheduler: &osc::Scheduler,
+  sender: &osc::Connection,
+  packet: osc::Packet,
+) -> Result<(), Failure> {
+  let timetag = match &packet {
+    osc::Packet::Bundle(bundle) if is_scheduled(&packet) => bundle.timetag,
+    _ => return send_packet(sender, packet).map(|_| ()),
+  };
+  let now = SystemTime::now();
+  println!(
+    "{}{}",
+    "\n🕒 [SCHEDULED]: ".cyan().dimmed(),
+    format!(
+      "bundle of {} element(s) for {} ({})",
+      bundle_len(&packet),
+      time::iso8601(timetag),
+      render::relative(timetag, now)
+    )
+    .white()
+    .dimmed()
+  );
+  scheduler.schedule(packet, time::system_from_ntp(timetag));
+  Ok(())
+}
+
+// Whether `packet` is a bundle to be sent at the time of its timetag rather than right away.
+fn is_scheduled(packet: &osc::Packet) -> bool {
+  match packet {
+    osc::Packet::Bundle(bundle) => !time::is_immediate(bundle.timetag),
+    osc::Packet::Message(_) => false,
+  }
+}
+
+fn bundle_len(packet: &osc::Packet) -> usize {
+  match packet {
+    osc::Packet::Bundle(bundle) => bundle.content.len(),
+    osc::Packet::Message(_) => 1,
+  }
+}
+
+// How late the scheduler sent the packets so far.
+#[derive(Default)]
+struct Jitter {
+  count: u32,
+  last: Duration,
+  max: Duration,
+  total: Duration,
+}
+
+impl Jitter {
+  fn record(&mut self, jitter: Duration) {
+    self.count += 1;
+    self.last = jitter;
+    self.max = self.max.max(jitter);
+    self.total += jitter;
+  }
+}
+
+impl fmt::Display for Jitter {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let millis = |d: Duration| d.as_secs_f64() * 1_000.0;
+    write!(
+      f,
+      "sent {:.3} ms after its timetag, mean {:.3} ms, max {:.3} ms over {} scheduled packet{}",
+      millis(self.last),
+      millis(self.total / self.count.max(1)),
+      millis(self.max),
+      self.count,
+      if self.count == 1 { "" } else { "s" }
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_fatal_errors() {
+    // Nothing is left to listen on.
+    let stopped = Input::new(Output::Table).recv().unwrap_err();
+    assert!(is_fatal(&stopped));
+    assert!(is_fatal(&osc::CommunicationError::Poisoned));
+
+    // The proxy carries on after an ICMP "port unreachable" or a reset reported by its socket.
+    for (kind, fatal) in [
+      (ErrorKind::ConnectionRefused, false),
+      (ErrorKind::ConnectionReset, false),
+      (ErrorKind::Interrupted, false),
+      (ErrorKind::WouldBlock, false),
+      (ErrorKind::TimedOut, false),
+      (ErrorKind::BrokenPipe, true),
+      (ErrorKind::InvalidInput, true),
+      (ErrorKind::NotConnected, true),
+    ]
+    .iter()
+    {
+      let error = osc::CommunicationError::Io(std::io::Error::from(*kind));
+      assert_eq!(is_fatal(&error), *fatal, "{:?}", kind);
+    }
+  }
+
+  #[test]
+  fn test_session_socket() {
+    let target =
+      osc::Receiver::bind_to_with_mtu("127.0.0.1:0", osc::recv::MAX_UDP_PAYLOAD).unwrap();
+    let sender = connect(&Target {
+      host: "127.0.0.1".to_string(),
+      port: target.local_addr().unwrap().port(),
+      transport: osc::Transport::Udp,
+      options: osc::SendOptions::default(),
+    })
+    .unwrap();
+    sender
+      .set_read_timeout(Some(Duration::from_secs(1)))
+      .unwrap();
+
+    // Every packet goes out of the same socket, which replies are sent back to.
+    let mut sources = vec![];
+    for addr in ["/s_new", "/n_free"].iter() {
+      let packet = osc::Packet::from(osc::msg(*addr, vec![osc::Type::Int(1)]));
+      send_packet(&sender, packet.clone()).unwrap();
+      let (bytes, source) = target.recv_bytes().unwrap();
+      assert_eq!(osc::decode(&bytes).unwrap(), packet);
+      sources.push(source);
+    }
+    assert_eq!(sources[0], sources[1]);
+    let done = osc::msg("/done", vec![osc::Type::String("/n_free".to_string())]);
+    let reply = osc::encode(osc::Packet::from(done)).unwrap();
+    target.send_bytes_to(&reply, sources[0]).unwrap();
+    assert_eq!(sender.recv_bytes().unwrap(), reply);
+  }
+
+  #[test]
+  fn test_expected_replies() {
+    let status = osc::Packet::from(osc::msg("/status.reply", vec![osc::Type::Int(1)]));
+    let done = osc::Packet::Bundle(osc::Bundle {
+      timetag: osc::rosc::OscTime {
+        seconds: 0,
+        fractional: 1,
+      },
+      content: vec![osc::Packet::from(osc::msg("/done", vec![])).into()],
+    });
+    let cases = [
+      (&status, None, true),
+      (&done, None, true),
+      (&status, Some("/status.reply"), true),
+      (&status, Some("/status*"), true),
+      (&status, Some("/done"), false),
+      (&done, Some("/done"), true),
+      (&done, Some("/fail"), false),
+    ];
+    for (packet, expect, expected) in cases.iter() {
+      let pattern = expect.map(|expect| Pattern::parse(expect).unwrap());
+      assert_eq!(
+        is_expected(packet, pattern.as_ref()),
+        *expected,
+        "{:?}",
+        expect
+      );
+    }
+  }
+
+  #[test]
+  fn test_parse_lines() {
+    let cases = [
+      (Syntax::Literal, "/a 1", Ok(())),
+      (Syntax::Literal, "  #bundle @now+1s {/a; /b 2.5}", Ok(())),
+      (Syntax::Literal, r#"/a "x"#, Err(Failure::Lex)),
+      (Syntax::Literal, "1 2", Err(Failure::Parse)),
+      (
+        Syntax::Json,
+        r#"{"address":"/a","args":[{"type":"i","value":1}]}"#,
+        Ok(()),
+      ),
+      (
+        Syntax::Json,
+        r#"{"address":"/a","args":[1]}"#,
+        Err(Failure::Parse),
+      ),
+      (Syntax::Json, r#"{"address":"/a","#, Err(Failure::Parse)),
+      (Syntax::Json, "/a 1", Err(Failure::Parse)),
+    ];
+    for (syntax, line, expected) in cases.iter() {
+      let parsed = parse_line("test", 1, line, *syntax).map(|_| ());
+      assert_eq!(parsed, *expected, "{}", line);
+    }
+  }
+
+  #[test]
+  fn test_scheduled_packets() {
+    let cases = [
+      ("/a 1", false),
+      ("#bundle @0.0000000002 {/a 1}", false),
+      ("#bundle @now+1s {/a 1}", true),
+      ("#bundle @2026-10-17T20:00:00Z {/a 1}", true),
+    ];
+    for (line, scheduled) in cases.iter() {
+      let packet = parse_line("test", 1, line, Syntax::Literal).unwrap();
+      assert_eq!(is_scheduled(&packet), *scheduled, "{}", line);
+    }
+  }
+
+  #[test]
+  fn test_send_lines() {
+    let input = "/a 1\n\n  \n/b \"x\n/c 2\n";
+    let cases = [
+      (false, vec![1, 4], Some(Failure::Lex)),
+      (true, vec![1, 4, 5], Some(Failure::Lex)),
+    ];
+    for (keep_going, numbers, failure) in cases.iter() {
+      let mut sent = vec![];
+      let result = send_lines(input.as_bytes(), *keep_going, |number, line| {
+        sent.push(number);
+        parse_line("stdin", number, line, Syntax::Literal).map(|_| ())
+      });
+      assert_eq!(result.unwrap(), *failure, "{}", keep_going);
+      assert_eq!(sent, *numbers, "{}", keep_going);
+    }
+    assert_eq!(
+      send_lines("/a 1\n\n/b\n".as_bytes(), false, |_, _| Ok(())).unwrap(),
+      None
+    );
+
+    // Lines that are not valid UTF-8 cannot be read, the error is left to the caller.
+    let mut sent = vec![];
+    let result = send_lines(&b"/a 1\n\xff\n/b\n"[..], true, |number, _| {
+      sent.push(number);
+      Ok(())
+    });
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    assert_eq!(sent, vec![1]);
+  }
+
+  #[test]
+  fn test_failing_ports() {
+    let mut input = Input::new(Output::Json);
+    for port in [9000, 9001].iter() {
+      input.ports.push(Local {
+        port: *port,
+        proto: Proto::Udp,
+        tag: None,
+      });
+    }
+    let listening = input.sender.clone().unwrap();
+    let source = SocketAddr::from(([127, 0, 0, 1], 57120));
+    let failed = Err(osc::CommunicationError::Io(ErrorKind::NotConnected.into()));
+    let refused = Err(osc::CommunicationError::Io(
+      ErrorKind::ConnectionRefused.into(),
+    ));
+    listening.send((0, failed)).unwrap();
+    listening.send((1, refused)).unwrap();
+    listening
+      .send((1, Ok(Incoming::Datagram(vec![1], source))))
+      .unwrap();
+    drop(listening);
+
+    // The first port is no longer listened on, the second one still is.
+    assert!(matches!(input.recv(), Ok(None)));
+    assert!(matches!(input.recv(), Err(e) if !is_fatal(&e)));
+    assert!(matches!(input.recv(), Ok(Some((1, _, _)))));
+    assert!(matches!(input.recv(), Err(e) if is_fatal(&e)));
   }
 }
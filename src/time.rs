@@ -0,0 +1,313 @@
+//! Durations as they are typed on the command line, eg. `250ms`, `1.5s` or `2m`, and conversions
+//! between wall-clock time and OSC timetags.
+//!
+//! An OSC timetag is an NTP timestamp: 32 bits of seconds since 1900-01-01 UTC followed by 32 bits
+//! of fraction of a second, in units of 2^-32 seconds.
+
+use rosc::OscTime;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Seconds between the NTP epoch (1900-01-01) and the Unix epoch (1970-01-01).
+pub const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// Parses a non-negative duration made of a number followed by a unit, `ms`, `s`, `m` or `h`.
+/// A number without a unit is taken as seconds.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+  let input = input.trim();
+  let split = input
+    .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '_'))
+    .unwrap_or(input.len());
+  let (number, unit) = input.split_at(split);
+  // In milliseconds, so that whole milliseconds convert exactly.
+  let scale = match unit {
+    "ms" => 1.0,
+    "" | "s" => 1_000.0,
+    "m" => 60_000.0,
+    "h" => 3_600_000.0,
+    _ => {
+      return Err(format!(
+        "`{}` has an unknown unit `{}`, use one of ms, s, m or h",
+        input, unit
+      ))
+    }
+  };
+  match number.replace('_', "").parse::<f64>() {
+    Ok(value) if value.is_finite() => Duration::try_from_secs_f64(value * scale / 1_000.0)
+      .map_err(|_| format!("`{}` is too long a duration", input)),
+    _ => Err(format!(
+      "`{}` is not a valid duration, eg. 250ms or 1.5s",
+      input
+    )),
+  }
+}
+
+/// The timetag of the current time.
+pub fn ntp_now() -> OscTime {
+  ntp_from_system(SystemTime::now())
+}
+
+/// Converts a point in time to the nearest timetag.
+pub fn ntp_from_system(time: SystemTime) -> OscTime {
+  let unix_nanos = match time.duration_since(UNIX_EPOCH) {
+    Ok(since) => since.as_nanos() as i128,
+    Err(e) => -(e.duration().as_nanos() as i128),
+  };
+  ntp_from_unix_nanos(unix_nanos)
+}
+
+/// Returns whether the timetag is the special "immediately" value, `0.1` in raw NTP terms.
+pub fn is_immediate(time: OscTime) -> bool {
+  time.seconds == 0 && time.fractional == 1
+}
+
+/// How many seconds the timetag is ahead of `at`, negative when it is behind.
+pub fn lead(time: OscTime, at: SystemTime) -> f64 {
+  let time = system_from_ntp(time);
+  match time.duration_since(at) {
+    Ok(ahead) => ahead.as_secs_f64(),
+    Err(e) => -e.duration().as_secs_f64(),
+  }
+}
+
+/// Converts a timetag back to a point in time.
+///
+/// Seconds with the most significant bit cleared are taken to be in NTP era 1, which starts on
+/// 2036-02-07, as recommended by RFC 4330.
+pub fn system_from_ntp(time: OscTime) -> SystemTime {
+  let nanos = ((time.fractional as i128 * NANOS_PER_SEC) + (1 << 31)) >> 32;
+  let unix_nanos = unix_seconds(time) as i128 * NANOS_PER_SEC + nanos;
+  if unix_nanos >= 0 {
+    UNIX_EPOCH + Duration::from_nanos(unix_nanos as u64)
+  } else {
+    UNIX_EPOCH - Duration::from_nanos(unix_nanos.unsigned_abs() as u64)
+  }
+}
+
+/// Formats a timetag as an ISO-8601 UTC date and time, eg. `2026-10-17T20:00:00.5Z`.
+///
+/// The fraction of a second is written with as many digits as it takes to convert back to the
+/// exact same timetag.
+pub fn iso8601(time: OscTime) -> String {
+  let unix_seconds = unix_seconds(time);
+  let (year, month, day) = civil_from_days(unix_seconds.div_euclid(86_400));
+  let seconds_of_day = unix_seconds.rem_euclid(86_400);
+  let fraction = if time.fractional == 0 {
+    String::new()
+  } else {
+    format!(".{}", decimal_from_fraction(time.fractional))
+  };
+  format!(
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+    year,
+    month,
+    day,
+    seconds_of_day / 3_600,
+    seconds_of_day % 3_600 / 60,
+    seconds_of_day % 60,
+    fraction
+  )
+}
+
+// Whole seconds since the Unix epoch of a timetag, see `system_from_ntp` for the NTP era.
+fn unix_seconds(time: OscTime) -> i64 {
+  let mut seconds = time.seconds as i64;
+  if seconds & 0x8000_0000 == 0 {
+    seconds += 1 << 32;
+  }
+  seconds - NTP_UNIX_OFFSET as i64
+}
+
+/// Converts a UTC date and time, shifted by `offset` seconds east of UTC (eg. `+02:00` is
+/// `7200`), to a timetag.
+pub fn ntp_from_utc(
+  (year, month, day): (i64, u32, u32),
+  (hour, minute, second): (u32, u32, u32),
+  fraction: u64,
+  offset: i64,
+) -> OscTime {
+  let days = days_from_civil(year, month, day);
+  let unix_seconds =
+    days * 86_400 + (hour * 3_600 + minute * 60 + second) as i64 - offset + NTP_UNIX_OFFSET as i64;
+  from_fixed_point(((unix_seconds as i128) << 32) + fraction as i128)
+}
+
+/// Converts the digits following the decimal point of a number of seconds, eg. `23` for `.23`,
+/// to the nearest fraction in units of 2^-32 seconds. The result can be `2^32` when the digits
+/// round up to a whole second.
+pub fn fraction_from_decimal(digits: &str) -> u64 {
+  // 19 digits are well beyond the 2^-32 precision and keep the arithmetic within `u128`.
+  let digits = &digits[..digits.len().min(19)];
+  if digits.is_empty() {
+    return 0;
+  }
+  let value = digits.parse::<u128>().unwrap_or(0);
+  let scale = 10u128.pow(digits.len() as u32);
+  (((value << 32) + scale / 2) / scale) as u64
+}
+
+/// Converts a fraction in units of 2^-32 seconds to the shortest decimal digits that convert back
+/// to it, eg. `2147483648` is `5`.
+pub fn decimal_from_fraction(fraction: u32) -> String {
+  // 10 digits are enough to tell every fraction apart, 2^-32 being about 2.3e-10.
+  let value = ((fraction as u128) * 10_000_000_000 + (1 << 31)) >> 32;
+  let digits = format!("{:010}", value);
+  let shortest = (1..=10)
+    .map(|len| &digits[..len])
+    .find(|candidate| fraction_from_decimal(candidate) == fraction as u64)
+    .unwrap_or(&digits);
+  shortest.to_string()
+}
+
+fn ntp_from_unix_nanos(unix_nanos: i128) -> OscTime {
+  let ntp_nanos = unix_nanos + NTP_UNIX_OFFSET as i128 * NANOS_PER_SEC;
+  let seconds = ntp_nanos.div_euclid(NANOS_PER_SEC);
+  let nanos = ntp_nanos.rem_euclid(NANOS_PER_SEC);
+  let fraction = ((nanos << 32) + NANOS_PER_SEC / 2) / NANOS_PER_SEC;
+  from_fixed_point((seconds << 32) + fraction)
+}
+
+// Splits a 32.32 fixed point number of seconds into a timetag, wrapping the seconds around the
+// NTP era like the timestamps themselves do.
+fn from_fixed_point(value: i128) -> OscTime {
+  OscTime {
+    seconds: (value >> 32) as u32,
+    fractional: value as u32,
+  }
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year.rem_euclid(400);
+  let month = month as i64;
+  let day_of_year =
+    (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146_097 + day_of_era - 719_468
+}
+
+// The date of a number of days since 1970-01-01, the inverse of `days_from_civil`.
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let days = days + 719_468;
+  let era = days.div_euclid(146_097);
+  let day_of_era = days.rem_euclid(146_097);
+  let year_of_era =
+    (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month_index = (5 * day_of_year + 2) / 153;
+  let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+  let month = if month_index < 10 {
+    month_index + 3
+  } else {
+    month_index - 9
+  } as u32;
+  let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+  (year, month, day)
+}
+
+/// Returns the number of days in the given month, accounting for leap years.
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+  match month {
+    1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+    4 | 6 | 9 | 11 => 30,
+    2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+    2 => 28,
+    _ => 0,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_durations() {
+    let valid = [
+      ("250ms", Duration::from_millis(250)),
+      ("1.5s", Duration::from_millis(1500)),
+      ("2", Duration::from_secs(2)),
+      ("2m", Duration::from_secs(120)),
+      ("1h", Duration::from_secs(3600)),
+      ("1_000ms", Duration::from_secs(1)),
+    ];
+    for (input, expected) in valid.iter() {
+      assert_eq!(parse_duration(input), Ok(*expected), "{}", input);
+    }
+
+    for input in [
+      "",
+      "ms",
+      "-1s",
+      "1.5days",
+      "1..2s",
+      "99999999999999999999s",
+      "99999999999999999999h",
+    ]
+    .iter()
+    {
+      assert!(parse_duration(input).is_err(), "{}", input);
+    }
+  }
+
+  #[test]
+  fn test_fractions() {
+    assert_eq!(fraction_from_decimal("5"), 1 << 31);
+    assert_eq!(fraction_from_decimal("25"), 1 << 30);
+    assert_eq!(fraction_from_decimal("23"), 987_842_478);
+    assert_eq!(fraction_from_decimal(""), 0);
+    assert_eq!(fraction_from_decimal("9999999999999"), 1 << 32);
+
+    for fraction in [0, 1, 2, 1 << 31, 987_842_478, 123_456_789, u32::MAX].iter() {
+      let decimal = decimal_from_fraction(*fraction);
+      assert_eq!(
+        fraction_from_decimal(&decimal),
+        *fraction as u64,
+        "{}",
+        decimal
+      );
+    }
+    assert_eq!(decimal_from_fraction(1 << 31), "5");
+    assert_eq!(decimal_from_fraction(0), "0");
+  }
+
+  #[test]
+  fn test_ntp_conversions() {
+    let epoch = ntp_from_system(UNIX_EPOCH);
+    assert_eq!((epoch.seconds, epoch.fractional), (2_208_988_800, 0));
+
+    let half = ntp_from_system(UNIX_EPOCH + Duration::from_millis(1_500));
+    assert_eq!((half.seconds, half.fractional), (2_208_988_801, 1 << 31));
+    assert_eq!(
+      system_from_ntp(half),
+      UNIX_EPOCH + Duration::from_millis(1_500)
+    );
+
+    let utc = ntp_from_utc((2026, 10, 17), (20, 0, 0), 1 << 31, 0);
+    let unix = 1_792_267_200;
+    assert_eq!(
+      (utc.seconds, utc.fractional),
+      ((unix + NTP_UNIX_OFFSET) as u32, 1 << 31)
+    );
+    let shifted = ntp_from_utc((2026, 10, 17), (22, 0, 0), 1 << 31, 7_200);
+    assert_eq!(shifted, utc);
+
+    assert_eq!(iso8601(utc), "2026-10-17T20:00:00.5Z");
+    assert_eq!(iso8601(ntp_from_system(UNIX_EPOCH)), "1970-01-01T00:00:00Z");
+    for days in [-1, 0, 59, 365, 11_016, 20_743, 24_837, 51_000].iter() {
+      let (year, month, day) = civil_from_days(*days);
+      assert_eq!(days_from_civil(year, month, day), *days);
+    }
+
+    // After 2036-02-07 the seconds wrap around into NTP era 1.
+    let era1 = ntp_from_utc((2040, 1, 1), (0, 0, 0), 0, 0);
+    assert_eq!(
+      system_from_ntp(era1),
+      UNIX_EPOCH + Duration::from_secs(2_208_988_800)
+    );
+  }
+}